@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Lifecycle of a row in the `job_queue` table. A job starts `New`, is
+/// claimed into `Running` by a worker holding a lease, and is removed from
+/// the table entirely on `complete_job`/`fail_job` (without `requeue`)
+/// rather than tracked as a terminal status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    New,
+    Running,
+}
+
+impl ToString for JobStatus {
+    fn to_string(&self) -> String {
+        match self {
+            JobStatus::New => String::from("new"),
+            JobStatus::Running => String::from("running"),
+        }
+    }
+}
+
+impl std::str::FromStr for JobStatus {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new" => Ok(JobStatus::New),
+            "running" => Ok(JobStatus::Running),
+            _ => Err(anyhow::anyhow!("Unknown job status: {}", s)),
+        }
+    }
+}
+
+/// A unit of work handed off by the app to a pool of out-of-band workers,
+/// e.g. a run/block descriptor to execute. Workers pull jobs with
+/// `Store::claim_next_job`, periodically extend their lease with
+/// `heartbeat_job`, and report back with `complete_job`/`fail_job`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    id: i64,
+    queue: String,
+    payload: Value,
+    status: JobStatus,
+    heartbeat: u64,
+    created: u64,
+}
+
+impl Job {
+    pub fn new(
+        id: i64,
+        queue: &str,
+        payload: Value,
+        status: JobStatus,
+        heartbeat: u64,
+        created: u64,
+    ) -> Self {
+        Job {
+            id,
+            queue: queue.to_string(),
+            payload,
+            status,
+            heartbeat,
+            created,
+        }
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+    pub fn queue(&self) -> &str {
+        &self.queue
+    }
+    pub fn payload(&self) -> &Value {
+        &self.payload
+    }
+    pub fn status(&self) -> JobStatus {
+        self.status
+    }
+    pub fn heartbeat(&self) -> u64 {
+        self.heartbeat
+    }
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+}
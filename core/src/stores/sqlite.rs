@@ -0,0 +1,2949 @@
+use crate::blocks::block::BlockType;
+use crate::data_sources::data_source::{
+    DataSource, DataSourceConfig, Document, DocumentVersion, SearchFilter,
+};
+use crate::databases::database::{Database, DatabaseRow, DatabaseTable};
+use crate::databases::table_schema::TableSchema;
+use crate::dataset::Dataset;
+use crate::http::request::{HttpRequest, HttpResponse};
+use crate::job_queue::{Job, JobStatus};
+use crate::project::Project;
+use crate::providers::embedder::{EmbedderRequest, EmbedderVector};
+use crate::providers::llm::{LLMChatGeneration, LLMChatRequest, LLMGeneration, LLMRequest};
+use crate::run::{Run, RunStatus, RunType};
+use crate::stores::store::{
+    Store, SizeTargets, StoreStats, StoreTransaction, SQLITE_INDEXES, SQLITE_TABLES,
+};
+use crate::utils;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A `Store` implementation backed by a single SQLite file. Intended for
+/// local/embedded deployments (CI, demos, single-user) where running a
+/// Postgres instance is more infrastructure than the use case warrants.
+///
+/// Schema-wise this is a straight translation of `POSTGRES_TABLES`
+/// (`SQLITE_TABLES`/`SQLITE_INDEXES`): the `tags_array`/`parents` `TEXT[]`
+/// columns become join tables, and the `plpgsql` cleanup functions
+/// (`delete_project_runs`, `delete_project_datasets`, `delete_run`) are
+/// reimplemented as plain multi-statement transactions below rather than
+/// stored procedures, since SQLite has neither `plpgsql` nor `FUNCTION`.
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl SqliteStore {
+    pub async fn new(path: &str) -> Result<Self> {
+        // `PRAGMA foreign_keys` is per-connection, so it has to be set via
+        // the pool's connection init hook rather than once after `Pool::new`
+        // — otherwise only the one connection that ran it enforces FKs.
+        let manager = SqliteConnectionManager::file(path)
+            .with_init(|c| c.execute_batch("PRAGMA foreign_keys = ON;"));
+        let pool = Pool::new(manager)?;
+        let store = Self { pool };
+        store.init().await?;
+        Ok(store)
+    }
+
+    pub async fn new_in_memory() -> Result<Self> {
+        let manager = SqliteConnectionManager::memory()
+            .with_init(|c| c.execute_batch("PRAGMA foreign_keys = ON;"));
+        let pool = Pool::new(manager)?;
+        let store = Self { pool };
+        store.init().await?;
+        Ok(store)
+    }
+
+    async fn init(&self) -> Result<()> {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            for t in SQLITE_TABLES {
+                c.execute(t, [])?;
+            }
+            for i in SQLITE_INDEXES {
+                c.execute(i, [])?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+}
+
+/// Constructs a SQLite-backed `Store` for local/embedded deployments. Gated
+/// behind the `sqlite` feature so that Postgres-only deployments don't pull
+/// in `rusqlite`/`r2d2` as a dependency.
+///
+/// (This crate slice ships without a `Cargo.toml`, so the `sqlite` feature
+/// itself can't be declared/verified here; this cfg attribute documents the
+/// intended gate for when the manifest exists.)
+#[cfg(feature = "sqlite")]
+pub async fn sqlite(path: &str) -> Result<Box<dyn Store + Sync + Send>> {
+    Ok(Box::new(SqliteStore::new(path).await?))
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    async fn create_project(&self) -> Result<Project> {
+        let pool = self.pool.clone();
+        let id = tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute("INSERT INTO projects DEFAULT VALUES", [])?;
+            Ok::<_, anyhow::Error>(c.last_insert_rowid())
+        })
+        .await??;
+        Ok(Project::new_from_id(id))
+    }
+
+    async fn delete_project(&self, project: &Project) -> Result<()> {
+        let project_id = project.project_id();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+
+            // `delete_project_runs` equivalent.
+            let run_ids: Vec<i64> = {
+                let mut stmt = tx.prepare("SELECT id FROM runs WHERE project = ?1")?;
+                stmt.query_map([project_id], |r| r.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            for run_id in &run_ids {
+                let block_exec_ids: Vec<i64> = {
+                    let mut stmt =
+                        tx.prepare("SELECT block_execution FROM runs_joins WHERE run = ?1")?;
+                    stmt.query_map([run_id], |r| r.get(0))?
+                        .collect::<rusqlite::Result<_>>()?
+                };
+                tx.execute("DELETE FROM runs_joins WHERE run = ?1", [run_id])?;
+                for block_exec_id in block_exec_ids {
+                    // `block_executions` are content-addressed and deduped
+                    // across runs, so only delete the row once no other
+                    // run's `runs_joins` still references it.
+                    tx.execute(
+                        "DELETE FROM block_executions
+                         WHERE id = ?1 AND id NOT IN (SELECT block_execution FROM runs_joins)",
+                        [block_exec_id],
+                    )?;
+                }
+            }
+            tx.execute("DELETE FROM runs WHERE project = ?1", [project_id])?;
+
+            // `delete_project_datasets` equivalent.
+            let dataset_ids: Vec<i64> = {
+                let mut stmt = tx.prepare("SELECT id FROM datasets WHERE project = ?1")?;
+                stmt.query_map([project_id], |r| r.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            for dataset_id in &dataset_ids {
+                let point_ids: Vec<i64> = {
+                    let mut stmt =
+                        tx.prepare("SELECT point FROM datasets_joins WHERE dataset = ?1")?;
+                    stmt.query_map([dataset_id], |r| r.get(0))?
+                        .collect::<rusqlite::Result<_>>()?
+                };
+                tx.execute("DELETE FROM datasets_joins WHERE dataset = ?1", [dataset_id])?;
+                for point_id in point_ids {
+                    // Points are content-addressed and deduped across datasets,
+                    // so only delete once no `datasets_joins` row references it.
+                    tx.execute(
+                        "DELETE FROM datasets_points
+                         WHERE id = ?1 AND id NOT IN (SELECT point FROM datasets_joins)",
+                        [point_id],
+                    )?;
+                }
+            }
+            tx.execute("DELETE FROM datasets WHERE project = ?1", [project_id])?;
+
+            tx.execute("DELETE FROM specifications WHERE project = ?1", [project_id])?;
+            tx.execute("DELETE FROM cache WHERE project = ?1", [project_id])?;
+
+            let data_source_ids: Vec<i64> = {
+                let mut stmt = tx.prepare("SELECT id FROM data_sources WHERE project = ?1")?;
+                stmt.query_map([project_id], |r| r.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            for ds_id in data_source_ids {
+                let doc_ids: Vec<i64> = {
+                    let mut stmt =
+                        tx.prepare("SELECT id FROM data_sources_documents WHERE data_source = ?1")?;
+                    stmt.query_map([ds_id], |r| r.get(0))?
+                        .collect::<rusqlite::Result<_>>()?
+                };
+                for doc_id in doc_ids {
+                    tx.execute(
+                        "DELETE FROM data_sources_documents_tags WHERE document = ?1",
+                        [doc_id],
+                    )?;
+                    tx.execute(
+                        "DELETE FROM data_sources_documents_parents WHERE document = ?1",
+                        [doc_id],
+                    )?;
+                }
+                tx.execute(
+                    "DELETE FROM data_sources_documents WHERE data_source = ?1",
+                    [ds_id],
+                )?;
+
+                let database_ids: Vec<i64> = {
+                    let mut stmt =
+                        tx.prepare("SELECT id FROM databases WHERE data_source = ?1")?;
+                    stmt.query_map([ds_id], |r| r.get(0))?
+                        .collect::<rusqlite::Result<_>>()?
+                };
+                for db_id in database_ids {
+                    let table_ids: Vec<i64> = {
+                        let mut stmt =
+                            tx.prepare("SELECT id FROM databases_tables WHERE database = ?1")?;
+                        stmt.query_map([db_id], |r| r.get(0))?
+                            .collect::<rusqlite::Result<_>>()?
+                    };
+                    for table_id in table_ids {
+                        tx.execute(
+                            "DELETE FROM databases_rows WHERE database_table = ?1",
+                            [table_id],
+                        )?;
+                    }
+                    tx.execute("DELETE FROM databases_tables WHERE database = ?1", [db_id])?;
+                }
+                tx.execute("DELETE FROM databases WHERE data_source = ?1", [ds_id])?;
+
+                tx.execute("DELETE FROM data_sources WHERE id = ?1", [ds_id])?;
+            }
+
+            tx.execute("DELETE FROM job_queue WHERE project = ?1", [project_id])?;
+            tx.execute("DELETE FROM alias WHERE project = ?1", [project_id])?;
+
+            tx.execute("DELETE FROM projects WHERE id = ?1", [project_id])?;
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn latest_dataset_hash(
+        &self,
+        project: &Project,
+        dataset_id: &str,
+    ) -> Result<Option<String>> {
+        let project_id = project.project_id();
+        let dataset_id = dataset_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let hash = c
+                .query_row(
+                    "SELECT hash FROM datasets WHERE project = ?1 AND dataset_id = ?2
+                     ORDER BY created DESC LIMIT 1",
+                    rusqlite::params![project_id, dataset_id],
+                    |r| r.get(0),
+                )
+                .ok();
+            Ok::<_, anyhow::Error>(hash)
+        })
+        .await?
+    }
+
+    async fn register_dataset(&self, project: &Project, d: &Dataset) -> Result<()> {
+        let project_id = project.project_id();
+        let dataset_id = d.dataset_id().to_string();
+        let hash = d.hash().to_string();
+        let data = d.iter().map(|p| p.clone()).collect::<Vec<_>>();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            tx.execute(
+                "INSERT INTO datasets (project, created, dataset_id, hash)
+                 VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![project_id, utils::now(), dataset_id, hash],
+            )?;
+            let dataset_row_id = tx.last_insert_rowid();
+            for (idx, point) in data.iter().enumerate() {
+                let json = serde_json::to_string(point)?;
+                let point_hash = utils::hash(json.as_bytes());
+                tx.execute(
+                    "INSERT OR IGNORE INTO datasets_points (hash, json) VALUES (?1, ?2)",
+                    rusqlite::params![point_hash, json],
+                )?;
+                let point_id: i64 = tx.query_row(
+                    "SELECT id FROM datasets_points WHERE hash = ?1",
+                    [&point_hash],
+                    |r| r.get(0),
+                )?;
+                tx.execute(
+                    "INSERT INTO datasets_joins (dataset, point, point_idx) VALUES (?1, ?2, ?3)",
+                    rusqlite::params![dataset_row_id, point_id, idx as i64],
+                )?;
+            }
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn load_dataset(
+        &self,
+        project: &Project,
+        dataset_id: &str,
+        hash: &str,
+    ) -> Result<Option<Dataset>> {
+        let project_id = project.project_id();
+        let dataset_id = dataset_id.to_string();
+        let hash = hash.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let dataset_row_id: Option<i64> = c
+                .query_row(
+                    "SELECT id FROM datasets WHERE project = ?1 AND dataset_id = ?2 AND hash = ?3",
+                    rusqlite::params![project_id, dataset_id, hash],
+                    |r| r.get(0),
+                )
+                .ok();
+            let dataset_row_id = match dataset_row_id {
+                Some(id) => id,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            let mut stmt = c.prepare(
+                "SELECT p.json FROM datasets_joins j
+                 JOIN datasets_points p ON p.id = j.point
+                 WHERE j.dataset = ?1 ORDER BY j.point_idx",
+            )?;
+            let points: Vec<serde_json::Value> = stmt
+                .query_map([dataset_row_id], |r| {
+                    let json: String = r.get(0)?;
+                    Ok(json)
+                })?
+                .collect::<rusqlite::Result<Vec<String>>>()?
+                .into_iter()
+                .map(|json| serde_json::from_str(&json))
+                .collect::<Result<_, _>>()?;
+            Ok(Some(Dataset::new_with_data(&dataset_id, &hash, points)?))
+        })
+        .await?
+    }
+
+    async fn list_datasets(
+        &self,
+        project: &Project,
+    ) -> Result<HashMap<String, Vec<(String, u64)>>> {
+        let project_id = project.project_id();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let mut stmt = c.prepare(
+                "SELECT dataset_id, hash, created FROM datasets WHERE project = ?1
+                 ORDER BY dataset_id, created DESC",
+            )?;
+            let rows = stmt.query_map([project_id], |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    r.get::<_, String>(1)?,
+                    r.get::<_, i64>(2)?,
+                ))
+            })?;
+            let mut out: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+            for row in rows {
+                let (dataset_id, hash, created) = row?;
+                out.entry(dataset_id).or_default().push((hash, created as u64));
+            }
+            Ok::<_, anyhow::Error>(out)
+        })
+        .await?
+    }
+
+    async fn latest_specification_hash(&self, project: &Project) -> Result<Option<String>> {
+        let project_id = project.project_id();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let hash = c
+                .query_row(
+                    "SELECT hash FROM specifications WHERE project = ?1
+                     ORDER BY created DESC LIMIT 1",
+                    [project_id],
+                    |r| r.get(0),
+                )
+                .ok();
+            Ok::<_, anyhow::Error>(hash)
+        })
+        .await?
+    }
+
+    async fn register_specification(
+        &self,
+        project: &Project,
+        hash: &str,
+        spec: &str,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let hash = hash.to_string();
+        let spec = spec.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let exists: Option<i64> = c
+                .query_row(
+                    "SELECT id FROM specifications WHERE project = ?1 AND hash = ?2",
+                    rusqlite::params![project_id, hash],
+                    |r| r.get(0),
+                )
+                .ok();
+            if exists.is_none() {
+                c.execute(
+                    "INSERT INTO specifications (project, created, hash, specification)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    rusqlite::params![project_id, utils::now(), hash, spec],
+                )?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn load_specification(
+        &self,
+        project: &Project,
+        hash: &str,
+    ) -> Result<Option<(u64, String)>> {
+        let project_id = project.project_id();
+        let hash = hash.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let row = c
+                .query_row(
+                    "SELECT created, specification FROM specifications
+                     WHERE project = ?1 AND hash = ?2",
+                    rusqlite::params![project_id, hash],
+                    |r| Ok((r.get::<_, i64>(0)? as u64, r.get::<_, String>(1)?)),
+                )
+                .ok();
+            Ok::<_, anyhow::Error>(row)
+        })
+        .await?
+    }
+
+    async fn latest_run_id(&self, project: &Project, run_type: RunType) -> Result<Option<String>> {
+        let project_id = project.project_id();
+        let run_type = run_type.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let run_id = c
+                .query_row(
+                    "SELECT run_id FROM runs WHERE project = ?1 AND run_type = ?2
+                     ORDER BY created DESC LIMIT 1",
+                    rusqlite::params![project_id, run_type],
+                    |r| r.get(0),
+                )
+                .ok();
+            Ok::<_, anyhow::Error>(run_id)
+        })
+        .await?
+    }
+
+    async fn list_runs(
+        &self,
+        project: &Project,
+        run_type: RunType,
+        limit_offset: Option<(usize, usize)>,
+    ) -> Result<(Vec<Run>, usize)> {
+        let project_id = project.project_id();
+        let run_type_s = run_type.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let total: i64 = c.query_row(
+                "SELECT COUNT(*) FROM runs WHERE project = ?1 AND run_type = ?2",
+                rusqlite::params![project_id, run_type_s],
+                |r| r.get(0),
+            )?;
+            let (limit, offset) = limit_offset.unwrap_or((usize::MAX, 0));
+            let mut stmt = c.prepare(
+                "SELECT run_id, status_json FROM runs WHERE project = ?1 AND run_type = ?2
+                 ORDER BY created DESC LIMIT ?3 OFFSET ?4",
+            )?;
+            let runs = stmt
+                .query_map(
+                    rusqlite::params![project_id, run_type_s, limit as i64, offset as i64],
+                    |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)),
+                )?
+                .map(|row| {
+                    let (run_id, status_json) = row?;
+                    let status: RunStatus = serde_json::from_str(&status_json)?;
+                    Ok::<_, anyhow::Error>(Run::new_from_id_status(&run_id, run_type, status))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok::<_, anyhow::Error>((runs, total as usize))
+        })
+        .await?
+    }
+
+    async fn load_runs(
+        &self,
+        project: &Project,
+        run_ids: Vec<String>,
+    ) -> Result<HashMap<String, Run>> {
+        let mut out = HashMap::new();
+        for run_id in run_ids {
+            if let Some(run) = self.load_run(project, &run_id, None).await? {
+                out.insert(run_id, run);
+            }
+        }
+        Ok(out)
+    }
+
+    async fn create_run_empty(&self, project: &Project, run: &Run) -> Result<()> {
+        let project_id = project.project_id();
+        let run_id = run.run_id().to_string();
+        let run_type = run.run_type().to_string();
+        let app_hash = run.app_hash().to_string();
+        let config_json = serde_json::to_string(run.config())?;
+        let status_json = serde_json::to_string(run.status())?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "INSERT INTO runs (project, created, run_id, run_type, app_hash,
+                                    config_json, status_json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                rusqlite::params![
+                    project_id,
+                    utils::now(),
+                    run_id,
+                    run_type,
+                    app_hash,
+                    config_json,
+                    status_json
+                ],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn update_run_status(
+        &self,
+        project: &Project,
+        run_id: &str,
+        run_status: &RunStatus,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let run_id = run_id.to_string();
+        let status_json = serde_json::to_string(run_status)?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "UPDATE runs SET status_json = ?1 WHERE project = ?2 AND run_id = ?3",
+                rusqlite::params![status_json, project_id, run_id],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn append_run_block(
+        &self,
+        project: &Project,
+        run: &Run,
+        block_idx: usize,
+        block_type: &BlockType,
+        block_name: &String,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let run_id = run.run_id().to_string();
+        let block_type = block_type.to_string();
+        let block_name = block_name.clone();
+        let executions = run.execution_for_block(block_idx)?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let run_row_id: i64 = tx.query_row(
+                "SELECT id FROM runs WHERE project = ?1 AND run_id = ?2",
+                rusqlite::params![project_id, run_id],
+                |r| r.get(0),
+            )?;
+            for (input_idx, map) in executions.into_iter().enumerate() {
+                for (map_idx, execution) in map.into_iter().enumerate() {
+                    let json = serde_json::to_string(&execution)?;
+                    let hash = utils::hash(json.as_bytes());
+                    tx.execute(
+                        "INSERT OR IGNORE INTO block_executions (hash, execution)
+                         VALUES (?1, ?2)",
+                        rusqlite::params![hash, json],
+                    )?;
+                    let block_execution_id: i64 = tx.query_row(
+                        "SELECT id FROM block_executions WHERE hash = ?1",
+                        [&hash],
+                        |r| r.get(0),
+                    )?;
+                    tx.execute(
+                        "INSERT INTO runs_joins
+                         (run, block_idx, block_type, block_name, input_idx, map_idx, block_execution)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        rusqlite::params![
+                            run_row_id,
+                            block_idx as i64,
+                            block_type,
+                            block_name,
+                            input_idx as i64,
+                            map_idx as i64,
+                            block_execution_id
+                        ],
+                    )?;
+                }
+            }
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn load_run(
+        &self,
+        project: &Project,
+        run_id: &str,
+        block: Option<Option<(BlockType, String)>>,
+    ) -> Result<Option<Run>> {
+        let project_id = project.project_id();
+        let run_id = run_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let row = c
+                .query_row(
+                    "SELECT run_type, app_hash, config_json, status_json FROM runs
+                     WHERE project = ?1 AND run_id = ?2",
+                    rusqlite::params![project_id, run_id],
+                    |r| {
+                        Ok((
+                            r.get::<_, String>(0)?,
+                            r.get::<_, String>(1)?,
+                            r.get::<_, String>(2)?,
+                            r.get::<_, String>(3)?,
+                        ))
+                    },
+                )
+                .ok();
+            let (run_type_s, app_hash, config_json, status_json) = match row {
+                Some(row) => row,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            let run_type: RunType = run_type_s.parse()?;
+            let status: RunStatus = serde_json::from_str(&status_json)?;
+            let config = serde_json::from_str(&config_json)?;
+
+            let mut run = Run::new_from_id_status_config(&run_id, run_type, &app_hash, status, config);
+
+            // `None` loads every block, `Some(Some(_))` only that block and
+            // `Some(None)` none at all.
+            let block_filter: Option<(Option<String>, Option<String>)> = match block {
+                None => Some((None, None)),
+                Some(Some((block_type, block_name))) => {
+                    Some((Some(block_type.to_string()), Some(block_name)))
+                }
+                Some(None) => None,
+            };
+            if let Some((block_type_filter, block_name_filter)) = block_filter {
+                let mut stmt = c.prepare(
+                    "SELECT rj.block_idx, rj.block_type, rj.block_name, rj.input_idx, rj.map_idx,
+                            be.execution
+                     FROM runs_joins rj
+                     JOIN block_executions be ON be.id = rj.block_execution
+                     JOIN runs r ON r.id = rj.run
+                     WHERE r.project = ?1 AND r.run_id = ?2
+                       AND (?3 IS NULL OR (rj.block_type = ?3 AND rj.block_name = ?4))
+                     ORDER BY rj.block_idx, rj.input_idx, rj.map_idx",
+                )?;
+                let params =
+                    rusqlite::params![project_id, run_id, block_type_filter, block_name_filter];
+                let rows = stmt.query_map(params, |r| {
+                    Ok((
+                        r.get::<_, i64>(0)?,
+                        r.get::<_, String>(1)?,
+                        r.get::<_, String>(2)?,
+                        r.get::<_, i64>(3)?,
+                        r.get::<_, i64>(4)?,
+                        r.get::<_, String>(5)?,
+                    ))
+                })?;
+                for row in rows {
+                    let (block_idx, block_type, block_name, input_idx, map_idx, execution_json) =
+                        row?;
+                    run.set_block_execution(
+                        block_idx as usize,
+                        &block_type,
+                        &block_name,
+                        input_idx as usize,
+                        map_idx as usize,
+                        serde_json::from_str(&execution_json)?,
+                    );
+                }
+            }
+
+            Ok(Some(run))
+        })
+        .await?
+    }
+
+    async fn delete_run(&self, project: &Project, run_id: &str) -> Result<()> {
+        let project_id = project.project_id();
+        let run_id = run_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            // `delete_run` equivalent.
+            let run_row_id: Option<i64> = tx
+                .query_row(
+                    "SELECT id FROM runs WHERE project = ?1 AND run_id = ?2",
+                    rusqlite::params![project_id, run_id],
+                    |r| r.get(0),
+                )
+                .ok();
+            let run_row_id = match run_row_id {
+                Some(id) => id,
+                None => return Ok::<_, anyhow::Error>(()),
+            };
+            let block_exec_ids: Vec<i64> = {
+                let mut stmt = tx.prepare("SELECT block_execution FROM runs_joins WHERE run = ?1")?;
+                stmt.query_map([run_row_id], |r| r.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            tx.execute("DELETE FROM runs_joins WHERE run = ?1", [run_row_id])?;
+            for id in block_exec_ids {
+                // Content-addressed and deduped across runs — only delete
+                // once no other run's `runs_joins` still references it.
+                tx.execute(
+                    "DELETE FROM block_executions
+                     WHERE id = ?1 AND id NOT IN (SELECT block_execution FROM runs_joins)",
+                    [id],
+                )?;
+            }
+            tx.execute("DELETE FROM runs WHERE id = ?1", [run_row_id])?;
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn has_data_sources(&self, project: &Project) -> Result<bool> {
+        let project_id = project.project_id();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let count: i64 = c.query_row(
+                "SELECT COUNT(*) FROM data_sources WHERE project = ?1",
+                [project_id],
+                |r| r.get(0),
+            )?;
+            Ok::<_, anyhow::Error>(count > 0)
+        })
+        .await?
+    }
+
+    async fn register_data_source(&self, project: &Project, ds: &DataSource) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = ds.data_source_id().to_string();
+        let internal_id = ds.internal_id().to_string();
+        let config_json = serde_json::to_string(ds.config())?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "INSERT INTO data_sources (project, created, data_source_id, internal_id, config_json)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![project_id, utils::now(), data_source_id, internal_id, config_json],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn load_data_source(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+    ) -> Result<Option<DataSource>> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let row = c
+                .query_row(
+                    "SELECT internal_id, config_json FROM data_sources
+                     WHERE project = ?1 AND data_source_id = ?2",
+                    rusqlite::params![project_id, data_source_id],
+                    |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)),
+                )
+                .ok();
+            let (internal_id, config_json) = match row {
+                Some(row) => row,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            let config: DataSourceConfig = serde_json::from_str(&config_json)?;
+            Ok(Some(DataSource::new_from_id(
+                &data_source_id,
+                &internal_id,
+                config,
+            )))
+        })
+        .await?
+    }
+
+    async fn update_data_source_config(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        config: &DataSourceConfig,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let config_json = serde_json::to_string(config)?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "UPDATE data_sources SET config_json = ?1
+                 WHERE project = ?2 AND data_source_id = ?3",
+                rusqlite::params![config_json, project_id, data_source_id],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn load_data_source_document(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document_id: &str,
+        version_hash: &Option<String>,
+    ) -> Result<Option<Document>> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document_id.to_string();
+        let version_hash = version_hash.clone();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let row_id: Option<i64> = match &version_hash {
+                Some(hash) => c
+                    .query_row(
+                        "SELECT d.id FROM data_sources_documents d
+                         JOIN data_sources ds ON ds.id = d.data_source
+                         WHERE ds.project = ?1 AND ds.data_source_id = ?2
+                               AND d.document_id = ?3 AND d.hash = ?4",
+                        rusqlite::params![project_id, data_source_id, document_id, hash],
+                        |r| r.get(0),
+                    )
+                    .ok(),
+                None => c
+                    .query_row(
+                        "SELECT d.id FROM data_sources_documents d
+                         JOIN data_sources ds ON ds.id = d.data_source
+                         WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.document_id = ?3
+                         ORDER BY d.created DESC LIMIT 1",
+                        rusqlite::params![project_id, data_source_id, document_id],
+                        |r| r.get(0),
+                    )
+                    .ok(),
+            };
+            let row_id = match row_id {
+                Some(id) => id,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            Ok(Some(load_document_row(&c, row_id)?))
+        })
+        .await?
+    }
+
+    async fn find_data_source_document_ids(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        filter: &Option<SearchFilter>,
+        limit_offset: Option<(usize, usize)>,
+    ) -> Result<(Vec<String>, usize)> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let filter = filter.clone();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let ds_row_id: i64 = c.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+
+            // `data_source = ?`/`status = 'latest'` plus one membership
+            // fragment per tag/parent clause in `filter`, matched against
+            // the join tables that stand in for the `tags_array`/`parents`
+            // GIN indexes (see SQLITE_TABLES).
+            let mut where_clause = "data_source = ? AND status = 'latest'".to_string();
+            let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(ds_row_id)];
+            if let Some(filter) = &filter {
+                push_membership_clause(
+                    &mut where_clause,
+                    &mut params,
+                    "data_sources_documents_tags",
+                    "tag",
+                    filter.tags.as_ref().and_then(|t| t.is_in.as_ref()),
+                    filter.tags.as_ref().and_then(|t| t.is_not.as_ref()),
+                );
+                push_membership_clause(
+                    &mut where_clause,
+                    &mut params,
+                    "data_sources_documents_parents",
+                    "parent",
+                    filter.parents.as_ref().and_then(|p| p.is_in.as_ref()),
+                    filter.parents.as_ref().and_then(|p| p.is_not.as_ref()),
+                );
+            }
+
+            let total: i64 = c.query_row(
+                &format!(
+                    "SELECT COUNT(DISTINCT document_id) FROM data_sources_documents
+                     WHERE {where_clause}"
+                ),
+                rusqlite::params_from_iter(params.iter()),
+                |r| r.get(0),
+            )?;
+
+            let (limit, offset) = limit_offset.unwrap_or((usize::MAX, 0));
+            params.push(Box::new(limit as i64));
+            params.push(Box::new(offset as i64));
+            let mut stmt = c.prepare(&format!(
+                "SELECT document_id FROM data_sources_documents
+                 WHERE {where_clause}
+                 ORDER BY timestamp DESC LIMIT ? OFFSET ?"
+            ))?;
+            let ids = stmt
+                .query_map(rusqlite::params_from_iter(params.iter()), |r| r.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok::<_, anyhow::Error>((ids, total as usize))
+        })
+        .await?
+    }
+
+    async fn upsert_data_source_document(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document: &Document,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document.document_id.clone();
+        let timestamp = document.timestamp as i64;
+        let tags = document.tags.clone();
+        let parents = document.parents.clone();
+        let source_url = document.source_url.clone();
+        let hash = document.hash.clone();
+        let text_size = document.text_size as i64;
+        let chunk_count = document.chunk_count as i64;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let ds_row_id: i64 = tx.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+            // Earlier versions of this document_id are superseded, mirroring
+            // how `status` tracks "latest" vs historical rows in Postgres.
+            tx.execute(
+                "UPDATE data_sources_documents SET status = 'superseded'
+                 WHERE data_source = ?1 AND document_id = ?2 AND status = 'latest'",
+                rusqlite::params![ds_row_id, document_id],
+            )?;
+            tx.execute(
+                "INSERT INTO data_sources_documents
+                 (data_source, created, document_id, timestamp, source_url, hash,
+                  text_size, chunk_count, status)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 'latest')",
+                rusqlite::params![
+                    ds_row_id,
+                    utils::now(),
+                    document_id,
+                    timestamp,
+                    source_url,
+                    hash,
+                    text_size,
+                    chunk_count
+                ],
+            )?;
+            let doc_row_id = tx.last_insert_rowid();
+            for tag in &tags {
+                tx.execute(
+                    "INSERT INTO data_sources_documents_tags (document, tag) VALUES (?1, ?2)",
+                    rusqlite::params![doc_row_id, tag],
+                )?;
+            }
+            for parent in &parents {
+                tx.execute(
+                    "INSERT INTO data_sources_documents_parents (document, parent) VALUES (?1, ?2)",
+                    rusqlite::params![doc_row_id, parent],
+                )?;
+            }
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn update_data_source_document_tags(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document_id: &str,
+        add_tags: &Vec<String>,
+        remove_tags: &Vec<String>,
+    ) -> Result<Vec<String>> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document_id.to_string();
+        let add_tags = add_tags.clone();
+        let remove_tags = remove_tags.clone();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let doc_row_id: i64 = tx.query_row(
+                "SELECT d.id FROM data_sources_documents d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2
+                       AND d.document_id = ?3 AND d.status = 'latest'",
+                rusqlite::params![project_id, data_source_id, document_id],
+                |r| r.get(0),
+            )?;
+            for tag in &remove_tags {
+                tx.execute(
+                    "DELETE FROM data_sources_documents_tags WHERE document = ?1 AND tag = ?2",
+                    rusqlite::params![doc_row_id, tag],
+                )?;
+            }
+            for tag in &add_tags {
+                tx.execute(
+                    "INSERT OR IGNORE INTO data_sources_documents_tags (document, tag)
+                     VALUES (?1, ?2)",
+                    rusqlite::params![doc_row_id, tag],
+                )?;
+            }
+            let mut stmt = tx.prepare(
+                "SELECT tag FROM data_sources_documents_tags WHERE document = ?1 ORDER BY tag",
+            )?;
+            let tags = stmt
+                .query_map([doc_row_id], |r| r.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(tags)
+        })
+        .await?
+    }
+
+    async fn update_data_source_document_parents(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document_id: &str,
+        parents: &Vec<String>,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document_id.to_string();
+        let parents = parents.clone();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let doc_row_id: i64 = tx.query_row(
+                "SELECT d.id FROM data_sources_documents d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2
+                       AND d.document_id = ?3 AND d.status = 'latest'",
+                rusqlite::params![project_id, data_source_id, document_id],
+                |r| r.get(0),
+            )?;
+            tx.execute(
+                "DELETE FROM data_sources_documents_parents WHERE document = ?1",
+                [doc_row_id],
+            )?;
+            for parent in &parents {
+                tx.execute(
+                    "INSERT INTO data_sources_documents_parents (document, parent)
+                     VALUES (?1, ?2)",
+                    rusqlite::params![doc_row_id, parent],
+                )?;
+            }
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn list_data_source_document_versions(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document_id: &str,
+        limit_offset: Option<(usize, usize)>,
+        latest_hash: &Option<String>,
+    ) -> Result<(Vec<DocumentVersion>, usize)> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document_id.to_string();
+        let latest_hash = latest_hash.clone();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let ds_row_id: i64 = c.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+            let total: i64 = c.query_row(
+                "SELECT COUNT(*) FROM data_sources_documents WHERE data_source = ?1 AND document_id = ?2",
+                rusqlite::params![ds_row_id, document_id],
+                |r| r.get(0),
+            )?;
+            let (limit, offset) = limit_offset.unwrap_or((usize::MAX, 0));
+            let mut stmt = c.prepare(
+                "SELECT created, hash FROM data_sources_documents
+                 WHERE data_source = ?1 AND document_id = ?2
+                 ORDER BY created DESC LIMIT ?3 OFFSET ?4",
+            )?;
+            let versions = stmt
+                .query_map(
+                    rusqlite::params![ds_row_id, document_id, limit as i64, offset as i64],
+                    |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)),
+                )?
+                .map(|row| {
+                    let (created, hash) = row?;
+                    Ok::<_, anyhow::Error>(DocumentVersion {
+                        hash,
+                        created: created as u64,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let versions = match latest_hash {
+                Some(hash) => versions.into_iter().filter(|v| v.hash == hash).collect(),
+                None => versions,
+            };
+            Ok::<_, anyhow::Error>((versions, total as usize))
+        })
+        .await?
+    }
+
+    async fn list_data_source_documents(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        limit_offset: Option<(usize, usize)>,
+        remove_system_tags: bool,
+    ) -> Result<(Vec<Document>, usize)> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let ds_row_id: i64 = c.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+            let total: i64 = c.query_row(
+                "SELECT COUNT(*) FROM data_sources_documents WHERE data_source = ?1 AND status = 'latest'",
+                [ds_row_id],
+                |r| r.get(0),
+            )?;
+            let (limit, offset) = limit_offset.unwrap_or((usize::MAX, 0));
+            let row_ids: Vec<i64> = {
+                let mut stmt = c.prepare(
+                    "SELECT id FROM data_sources_documents
+                     WHERE data_source = ?1 AND status = 'latest'
+                     ORDER BY timestamp DESC LIMIT ?2 OFFSET ?3",
+                )?;
+                stmt.query_map(
+                    rusqlite::params![ds_row_id, limit as i64, offset as i64],
+                    |r| r.get(0),
+                )?
+                .collect::<rusqlite::Result<_>>()?
+            };
+            let mut documents = row_ids
+                .into_iter()
+                .map(|id| load_document_row(&c, id))
+                .collect::<Result<Vec<_>>>()?;
+            if remove_system_tags {
+                for document in &mut documents {
+                    document.tags.retain(|t| !t.starts_with("__"));
+                }
+            }
+            Ok::<_, anyhow::Error>((documents, total as usize))
+        })
+        .await?
+    }
+
+    async fn delete_data_source_document(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document_id: &str,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let ds_row_id: i64 = tx.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+            let doc_row_ids: Vec<i64> = {
+                let mut stmt = tx.prepare(
+                    "SELECT id FROM data_sources_documents WHERE data_source = ?1 AND document_id = ?2",
+                )?;
+                stmt.query_map(rusqlite::params![ds_row_id, document_id], |r| r.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            for doc_id in doc_row_ids {
+                tx.execute(
+                    "DELETE FROM data_sources_documents_tags WHERE document = ?1",
+                    [doc_id],
+                )?;
+                tx.execute(
+                    "DELETE FROM data_sources_documents_parents WHERE document = ?1",
+                    [doc_id],
+                )?;
+                tx.execute("DELETE FROM data_sources_documents WHERE id = ?1", [doc_id])?;
+            }
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn delete_data_source(&self, project: &Project, data_source_id: &str) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let ds_row_id: i64 = tx.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+            let doc_row_ids: Vec<i64> = {
+                let mut stmt =
+                    tx.prepare("SELECT id FROM data_sources_documents WHERE data_source = ?1")?;
+                stmt.query_map([ds_row_id], |r| r.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            for doc_id in doc_row_ids {
+                tx.execute(
+                    "DELETE FROM data_sources_documents_tags WHERE document = ?1",
+                    [doc_id],
+                )?;
+                tx.execute(
+                    "DELETE FROM data_sources_documents_parents WHERE document = ?1",
+                    [doc_id],
+                )?;
+            }
+            tx.execute(
+                "DELETE FROM data_sources_documents WHERE data_source = ?1",
+                [ds_row_id],
+            )?;
+            tx.execute("DELETE FROM data_sources WHERE id = ?1", [ds_row_id])?;
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn register_database(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        name: &str,
+    ) -> Result<Database> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let name = name.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let ds_row_id: i64 = c.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+            let created = utils::now();
+            c.execute(
+                "INSERT INTO databases (created, data_source, database_id, name)
+                 VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![created, ds_row_id, database_id, name],
+            )?;
+            Ok::<_, anyhow::Error>(Database::new(
+                created as u64,
+                &data_source_id,
+                &database_id,
+                &name,
+            ))
+        })
+        .await?
+    }
+
+    async fn load_database(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+    ) -> Result<Option<Database>> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let row = c
+                .query_row(
+                    "SELECT d.created, d.name FROM databases d
+                     JOIN data_sources ds ON ds.id = d.data_source
+                     WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3",
+                    rusqlite::params![project_id, data_source_id, database_id],
+                    |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)),
+                )
+                .ok();
+            Ok::<_, anyhow::Error>(row.map(|(created, name)| {
+                Database::new(created as u64, &data_source_id, &database_id, &name)
+            }))
+        })
+        .await?
+    }
+
+    async fn list_databases(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        limit_offset: Option<(usize, usize)>,
+    ) -> Result<Vec<Database>> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let (limit, offset) = limit_offset.unwrap_or((usize::MAX, 0));
+            let mut stmt = c.prepare(
+                "SELECT d.created, d.database_id, d.name FROM databases d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2
+                 ORDER BY d.created DESC LIMIT ?3 OFFSET ?4",
+            )?;
+            let rows = stmt.query_map(
+                rusqlite::params![project_id, data_source_id, limit as i64, offset as i64],
+                |r| {
+                    Ok((
+                        r.get::<_, i64>(0)?,
+                        r.get::<_, String>(1)?,
+                        r.get::<_, String>(2)?,
+                    ))
+                },
+            )?;
+            let out = rows
+                .map(|row| {
+                    let (created, database_id, name) = row?;
+                    Ok::<_, anyhow::Error>(Database::new(
+                        created as u64,
+                        &data_source_id,
+                        &database_id,
+                        &name,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok::<_, anyhow::Error>(out)
+        })
+        .await?
+    }
+
+    async fn upsert_database_table(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        name: &str,
+        description: &str,
+    ) -> Result<DatabaseTable> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let name = name.to_string();
+        let description = description.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let db_row_id: i64 = c.query_row(
+                "SELECT d.id FROM databases d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3",
+                rusqlite::params![project_id, data_source_id, database_id],
+                |r| r.get(0),
+            )?;
+            let created = utils::now();
+            c.execute(
+                "INSERT INTO databases_tables (created, database, table_id, name, description)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(table_id, database) DO UPDATE SET name = ?4, description = ?5",
+                rusqlite::params![created, db_row_id, table_id, name, description],
+            )?;
+            Ok::<_, anyhow::Error>(DatabaseTable::new(
+                created as u64,
+                &table_id,
+                &name,
+                &description,
+                None,
+            ))
+        })
+        .await?
+    }
+
+    async fn update_database_table_schema(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        schema: &TableSchema,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let schema_json = serde_json::to_string(schema)?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "UPDATE databases_tables SET schema = ?1
+                 WHERE table_id = ?2 AND database = (
+                     SELECT d.id FROM databases d
+                     JOIN data_sources ds ON ds.id = d.data_source
+                     WHERE ds.project = ?3 AND ds.data_source_id = ?4 AND d.database_id = ?5
+                 )",
+                rusqlite::params![schema_json, table_id, project_id, data_source_id, database_id],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn load_database_table(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+    ) -> Result<Option<DatabaseTable>> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let row = c
+                .query_row(
+                    "SELECT t.created, t.name, t.description, t.schema FROM databases_tables t
+                     JOIN databases d ON d.id = t.database
+                     JOIN data_sources ds ON ds.id = d.data_source
+                     WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3
+                           AND t.table_id = ?4",
+                    rusqlite::params![project_id, data_source_id, database_id, table_id],
+                    |r| {
+                        Ok((
+                            r.get::<_, i64>(0)?,
+                            r.get::<_, String>(1)?,
+                            r.get::<_, String>(2)?,
+                            r.get::<_, Option<String>>(3)?,
+                        ))
+                    },
+                )
+                .ok();
+            let (created, name, description, schema_json) = match row {
+                Some(row) => row,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            let schema = schema_json.map(|j| serde_json::from_str(&j)).transpose()?;
+            Ok(Some(DatabaseTable::new(
+                created as u64,
+                &table_id,
+                &name,
+                &description,
+                schema,
+            )))
+        })
+        .await?
+    }
+
+    async fn list_databases_tables(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        limit_offset: Option<(usize, usize)>,
+    ) -> Result<(Vec<DatabaseTable>, usize)> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let db_row_id: i64 = c.query_row(
+                "SELECT d.id FROM databases d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3",
+                rusqlite::params![project_id, data_source_id, database_id],
+                |r| r.get(0),
+            )?;
+            let total: i64 = c.query_row(
+                "SELECT COUNT(*) FROM databases_tables WHERE database = ?1",
+                [db_row_id],
+                |r| r.get(0),
+            )?;
+            let (limit, offset) = limit_offset.unwrap_or((usize::MAX, 0));
+            let mut stmt = c.prepare(
+                "SELECT created, table_id, name, description, schema FROM databases_tables
+                 WHERE database = ?1 ORDER BY created DESC LIMIT ?2 OFFSET ?3",
+            )?;
+            let tables = stmt
+                .query_map(
+                    rusqlite::params![db_row_id, limit as i64, offset as i64],
+                    |r| {
+                        Ok((
+                            r.get::<_, i64>(0)?,
+                            r.get::<_, String>(1)?,
+                            r.get::<_, String>(2)?,
+                            r.get::<_, String>(3)?,
+                            r.get::<_, Option<String>>(4)?,
+                        ))
+                    },
+                )?
+                .map(|row| {
+                    let (created, table_id, name, description, schema_json) = row?;
+                    let schema = schema_json.map(|j| serde_json::from_str(&j)).transpose()?;
+                    Ok::<_, anyhow::Error>(DatabaseTable::new(
+                        created as u64,
+                        &table_id,
+                        &name,
+                        &description,
+                        schema,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok::<_, anyhow::Error>((tables, total as usize))
+        })
+        .await?
+    }
+
+    async fn batch_upsert_database_rows(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        rows: &Vec<DatabaseRow>,
+        truncate: bool,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let rows = rows
+            .iter()
+            .map(|r| Ok((r.row_id().to_string(), serde_json::to_string(r.content())?)))
+            .collect::<Result<Vec<(String, String)>>>()?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let table_row_id: i64 = tx.query_row(
+                "SELECT t.id FROM databases_tables t
+                 JOIN databases d ON d.id = t.database
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3
+                       AND t.table_id = ?4",
+                rusqlite::params![project_id, data_source_id, database_id, table_id],
+                |r| r.get(0),
+            )?;
+            if truncate {
+                tx.execute(
+                    "DELETE FROM databases_rows WHERE database_table = ?1",
+                    [table_row_id],
+                )?;
+            }
+            for (row_id, content_json) in rows {
+                tx.execute(
+                    "INSERT INTO databases_rows (created, database_table, content, row_id)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(row_id, database_table) DO UPDATE SET content = ?3",
+                    rusqlite::params![utils::now(), table_row_id, content_json, row_id],
+                )?;
+            }
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn load_database_row(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        row_id: &str,
+    ) -> Result<Option<DatabaseRow>> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let row_id = row_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let content_json: Option<String> = c
+                .query_row(
+                    "SELECT r.content FROM databases_rows r
+                     JOIN databases_tables t ON t.id = r.database_table
+                     JOIN databases d ON d.id = t.database
+                     JOIN data_sources ds ON ds.id = d.data_source
+                     WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3
+                           AND t.table_id = ?4 AND r.row_id = ?5",
+                    rusqlite::params![project_id, data_source_id, database_id, table_id, row_id],
+                    |r| r.get(0),
+                )
+                .ok();
+            let content_json = match content_json {
+                Some(json) => json,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            Ok(Some(DatabaseRow::new(
+                row_id,
+                serde_json::from_str(&content_json)?,
+            )))
+        })
+        .await?
+    }
+
+    async fn list_database_rows(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        limit_offset: Option<(usize, usize)>,
+    ) -> Result<(Vec<DatabaseRow>, usize)> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let table_row_id: i64 = c.query_row(
+                "SELECT t.id FROM databases_tables t
+                 JOIN databases d ON d.id = t.database
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3
+                       AND t.table_id = ?4",
+                rusqlite::params![project_id, data_source_id, database_id, table_id],
+                |r| r.get(0),
+            )?;
+            let total: i64 = c.query_row(
+                "SELECT COUNT(*) FROM databases_rows WHERE database_table = ?1",
+                [table_row_id],
+                |r| r.get(0),
+            )?;
+            let (limit, offset) = limit_offset.unwrap_or((usize::MAX, 0));
+            let mut stmt = c.prepare(
+                "SELECT row_id, content FROM databases_rows WHERE database_table = ?1
+                 ORDER BY row_id LIMIT ?2 OFFSET ?3",
+            )?;
+            let rows = stmt
+                .query_map(
+                    rusqlite::params![table_row_id, limit as i64, offset as i64],
+                    |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)),
+                )?
+                .map(|row| {
+                    let (row_id, content_json) = row?;
+                    Ok::<_, anyhow::Error>(DatabaseRow::new(
+                        row_id,
+                        serde_json::from_str(&content_json)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok::<_, anyhow::Error>((rows, total as usize))
+        })
+        .await?
+    }
+
+    async fn delete_database(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+            let db_row_id: i64 = tx.query_row(
+                "SELECT d.id FROM databases d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3",
+                rusqlite::params![project_id, data_source_id, database_id],
+                |r| r.get(0),
+            )?;
+            let table_ids: Vec<i64> = {
+                let mut stmt = tx.prepare("SELECT id FROM databases_tables WHERE database = ?1")?;
+                stmt.query_map([db_row_id], |r| r.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            for table_id in table_ids {
+                tx.execute(
+                    "DELETE FROM databases_rows WHERE database_table = ?1",
+                    [table_id],
+                )?;
+            }
+            tx.execute("DELETE FROM databases_tables WHERE database = ?1", [db_row_id])?;
+            tx.execute("DELETE FROM databases WHERE id = ?1", [db_row_id])?;
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn llm_cache_get(
+        &self,
+        project: &Project,
+        request: &LLMRequest,
+        max_age_ms: Option<u64>,
+    ) -> Result<Vec<LLMGeneration>> {
+        self.cache_get(project, request.hash(), max_age_ms).await
+    }
+
+    async fn llm_cache_store(
+        &self,
+        project: &Project,
+        request: &LLMRequest,
+        generation: &LLMGeneration,
+    ) -> Result<()> {
+        self.cache_store(project, request.hash(), &serde_json::to_string(request)?, generation)
+            .await
+    }
+
+    async fn llm_chat_cache_get(
+        &self,
+        project: &Project,
+        request: &LLMChatRequest,
+        max_age_ms: Option<u64>,
+    ) -> Result<Vec<LLMChatGeneration>> {
+        self.cache_get(project, request.hash(), max_age_ms).await
+    }
+
+    async fn llm_chat_cache_store(
+        &self,
+        project: &Project,
+        request: &LLMChatRequest,
+        generation: &LLMChatGeneration,
+    ) -> Result<()> {
+        self.cache_store(
+            project,
+            request.hash(),
+            &serde_json::to_string(request)?,
+            generation,
+        )
+        .await
+    }
+
+    async fn embedder_cache_get(
+        &self,
+        project: &Project,
+        request: &EmbedderRequest,
+        max_age_ms: Option<u64>,
+    ) -> Result<Vec<EmbedderVector>> {
+        self.cache_get(project, request.hash(), max_age_ms).await
+    }
+
+    async fn embedder_cache_store(
+        &self,
+        project: &Project,
+        request: &EmbedderRequest,
+        embedding: &EmbedderVector,
+    ) -> Result<()> {
+        self.cache_store(
+            project,
+            request.hash(),
+            &serde_json::to_string(request)?,
+            embedding,
+        )
+        .await
+    }
+
+    async fn http_cache_get(
+        &self,
+        project: &Project,
+        request: &HttpRequest,
+        max_age_ms: Option<u64>,
+    ) -> Result<Vec<HttpResponse>> {
+        self.cache_get(project, request.hash(), max_age_ms).await
+    }
+
+    async fn http_cache_store(
+        &self,
+        project: &Project,
+        request: &HttpRequest,
+        response: &HttpResponse,
+    ) -> Result<()> {
+        self.cache_store(
+            project,
+            request.hash(),
+            &serde_json::to_string(request)?,
+            response,
+        )
+        .await
+    }
+
+    async fn invalidate_cache(&self, project: &Project, hash: &str) -> Result<()> {
+        let project_id = project.project_id();
+        let hash = hash.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "DELETE FROM cache WHERE project = ?1 AND hash = ?2",
+                rusqlite::params![project_id, hash],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn invalidate_cache_by_age(
+        &self,
+        project: &Project,
+        older_than_ms: u64,
+    ) -> Result<usize> {
+        let project_id = project.project_id();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let cutoff = utils::now() as i64 - older_than_ms as i64;
+            let count = c.execute(
+                "DELETE FROM cache WHERE project = ?1 AND created < ?2",
+                rusqlite::params![project_id, cutoff],
+            )?;
+            Ok::<_, anyhow::Error>(count)
+        })
+        .await?
+    }
+
+    async fn enqueue_job(
+        &self,
+        project: &Project,
+        queue: &str,
+        payload: &serde_json::Value,
+    ) -> Result<i64> {
+        let project_id = project.project_id();
+        let queue = queue.to_string();
+        let payload_json = serde_json::to_string(payload)?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let now = utils::now() as i64;
+            c.execute(
+                "INSERT INTO job_queue (project, queue, payload, status, heartbeat, created)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                rusqlite::params![project_id, queue, payload_json, JobStatus::New.to_string(), now],
+            )?;
+            Ok::<_, anyhow::Error>(c.last_insert_rowid())
+        })
+        .await?
+    }
+
+    async fn claim_next_job(&self, queue: &str, lease_ms: u64) -> Result<Option<Job>> {
+        let queue = queue.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            // SQLite serializes writers, so a single `BEGIN IMMEDIATE`
+            // transaction is enough to make the claim atomic without
+            // Postgres' `SELECT ... FOR UPDATE SKIP LOCKED`.
+            let tx = c.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            let row = tx
+                .query_row(
+                    "SELECT id, payload, heartbeat, created FROM job_queue
+                     WHERE queue = ?1 AND status = ?2
+                     ORDER BY created ASC LIMIT 1",
+                    rusqlite::params![queue, JobStatus::New.to_string()],
+                    |r| {
+                        Ok((
+                            r.get::<_, i64>(0)?,
+                            r.get::<_, String>(1)?,
+                            r.get::<_, i64>(2)?,
+                            r.get::<_, i64>(3)?,
+                        ))
+                    },
+                )
+                .ok();
+            let (id, payload_json, _old_heartbeat, created) = match row {
+                Some(row) => row,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            let now = utils::now() as i64;
+            tx.execute(
+                "UPDATE job_queue SET status = ?1, heartbeat = ?2, lease_ms = ?3 WHERE id = ?4",
+                rusqlite::params![JobStatus::Running.to_string(), now, lease_ms as i64, id],
+            )?;
+            tx.commit()?;
+            Ok(Some(Job::new(
+                id,
+                &queue,
+                serde_json::from_str(&payload_json)?,
+                JobStatus::Running,
+                now as u64,
+                created as u64,
+            )))
+        })
+        .await?
+    }
+
+    async fn heartbeat_job(&self, job_id: i64) -> Result<()> {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "UPDATE job_queue SET heartbeat = ?1 WHERE id = ?2 AND status = ?3",
+                rusqlite::params![utils::now() as i64, job_id, JobStatus::Running.to_string()],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn complete_job(&self, job_id: i64) -> Result<()> {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute("DELETE FROM job_queue WHERE id = ?1", [job_id])?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn fail_job(&self, job_id: i64, requeue: bool) -> Result<()> {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            if requeue {
+                c.execute(
+                    "UPDATE job_queue SET status = ?1, heartbeat = ?2 WHERE id = ?3",
+                    rusqlite::params![JobStatus::New.to_string(), utils::now() as i64, job_id],
+                )?;
+            } else {
+                c.execute("DELETE FROM job_queue WHERE id = ?1", [job_id])?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn requeue_stale_jobs(&self, queue: &str, lease_ms: u64) -> Result<usize> {
+        let queue = queue.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let now = utils::now() as i64;
+            // Each row's own claimed `lease_ms` wins; the parameter is only a
+            // fallback for rows whose stored `lease_ms` is 0.
+            let count = c.execute(
+                "UPDATE job_queue SET status = ?1, heartbeat = ?2
+                 WHERE queue = ?3 AND status = ?4
+                   AND heartbeat < ?5 - (CASE WHEN lease_ms > 0 THEN lease_ms ELSE ?6 END)",
+                rusqlite::params![
+                    JobStatus::New.to_string(),
+                    now,
+                    queue,
+                    JobStatus::Running.to_string(),
+                    now,
+                    lease_ms as i64
+                ],
+            )?;
+            Ok::<_, anyhow::Error>(count)
+        })
+        .await?
+    }
+
+    async fn gc(&self, project: &Project, targets: SizeTargets) -> Result<()> {
+        let project_id = project.project_id();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut c = pool.get()?;
+            let tx = c.transaction()?;
+
+            // Orphan sweep: block_executions/datasets_points are content-addressed
+            // and have no `project` column, so a row with no surviving join is
+            // dead regardless of which project(s) used to reference it. A hash
+            // pinned via `alias` is protected even if currently unreferenced.
+            tx.execute(
+                "DELETE FROM block_executions
+                 WHERE id NOT IN (SELECT block_execution FROM runs_joins)
+                   AND hash NOT IN (SELECT hash FROM alias)",
+                [],
+            )?;
+            tx.execute(
+                "DELETE FROM datasets_points WHERE id NOT IN (SELECT point FROM datasets_joins)",
+                [],
+            )?;
+
+            if targets.max_rows.is_some() || targets.max_bytes.is_some() {
+                let (mut rows, mut bytes): (u64, u64) = tx.query_row(
+                    "SELECT COUNT(*), COALESCE(SUM(LENGTH(request) + LENGTH(response)), 0)
+                     FROM cache WHERE project = ?1",
+                    [project_id],
+                    |r| Ok((r.get::<_, i64>(0)? as u64, r.get::<_, i64>(1)? as u64)),
+                )?;
+
+                // Oldest-first.
+                let candidates: Vec<(i64, u64)> = {
+                    let mut stmt = tx.prepare(
+                        "SELECT id, LENGTH(request) + LENGTH(response) FROM cache
+                         WHERE project = ?1
+                         ORDER BY created ASC",
+                    )?;
+                    stmt.query_map([project_id], |r| {
+                        Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)? as u64))
+                    })?
+                    .collect::<rusqlite::Result<_>>()?
+                };
+
+                for (id, size) in candidates {
+                    let over_rows = targets.max_rows.is_some_and(|m| rows > m);
+                    let over_bytes = targets.max_bytes.is_some_and(|m| bytes > m);
+                    if !over_rows && !over_bytes {
+                        break;
+                    }
+                    tx.execute("DELETE FROM cache WHERE id = ?1", [id])?;
+                    rows -= 1;
+                    bytes -= size;
+                }
+            }
+
+            tx.commit()?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn pin(&self, project: &Project, name: &str, hash: &str) -> Result<()> {
+        let project_id = project.project_id();
+        let name = name.to_string();
+        let hash = hash.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "INSERT INTO alias (project, name, hash) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(project, name) DO UPDATE SET hash = excluded.hash",
+                rusqlite::params![project_id, name, hash],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn unpin(&self, project: &Project, name: &str) -> Result<()> {
+        let project_id = project.project_id();
+        let name = name.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "DELETE FROM alias WHERE project = ?1 AND name = ?2",
+                rusqlite::params![project_id, name],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn stats(&self, project: &Project) -> Result<StoreStats> {
+        let project_id = project.project_id();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            let (cache_rows, cache_bytes) = c.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(LENGTH(request) + LENGTH(response)), 0)
+                 FROM cache WHERE project = ?1",
+                [project_id],
+                |r| Ok((r.get::<_, i64>(0)? as u64, r.get::<_, i64>(1)? as u64)),
+            )?;
+            let (block_executions_rows, block_executions_bytes) = c.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(LENGTH(execution)), 0) FROM (
+                     SELECT DISTINCT be.id, be.execution FROM block_executions be
+                     JOIN runs_joins rj ON rj.block_execution = be.id
+                     JOIN runs r ON r.id = rj.run
+                     WHERE r.project = ?1
+                 )",
+                [project_id],
+                |r| Ok((r.get::<_, i64>(0)? as u64, r.get::<_, i64>(1)? as u64)),
+            )?;
+            let (datasets_points_rows, datasets_points_bytes) = c.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(LENGTH(json)), 0) FROM (
+                     SELECT DISTINCT dp.id, dp.json FROM datasets_points dp
+                     JOIN datasets_joins dj ON dj.point = dp.id
+                     JOIN datasets d ON d.id = dj.dataset
+                     WHERE d.project = ?1
+                 )",
+                [project_id],
+                |r| Ok((r.get::<_, i64>(0)? as u64, r.get::<_, i64>(1)? as u64)),
+            )?;
+            Ok::<_, anyhow::Error>(StoreStats {
+                cache_rows,
+                cache_bytes,
+                block_executions_rows,
+                block_executions_bytes,
+                datasets_points_rows,
+                datasets_points_bytes,
+            })
+        })
+        .await?
+    }
+
+    async fn begin(&self) -> Result<Box<dyn StoreTransaction + Sync + Send>> {
+        let pool = self.pool.clone();
+        let conn = tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute_batch("BEGIN IMMEDIATE")?;
+            Ok::<_, anyhow::Error>(c)
+        })
+        .await??;
+        Ok(Box::new(SqliteStoreTransaction {
+            conn: Arc::new(Mutex::new(Some(conn))),
+        }))
+    }
+
+    fn clone_box(&self) -> Box<dyn Store + Sync + Send> {
+        Box::new(self.clone())
+    }
+}
+
+/// A `StoreTransaction` backed by a single pooled connection holding an
+/// open `BEGIN IMMEDIATE`. Dropping this without a prior `commit`/`rollback`
+/// rolls back, so an early return via `?` can't leave a half-applied write
+/// committed.
+struct SqliteStoreTransaction {
+    conn: Arc<Mutex<Option<r2d2::PooledConnection<SqliteConnectionManager>>>>,
+}
+
+impl SqliteStoreTransaction {
+    // Runs `f` against the live connection on the blocking pool. Errors if
+    // the transaction has already been committed or rolled back.
+    async fn with_conn<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&rusqlite::Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let guard = conn.lock().map_err(|_| anyhow!("transaction mutex poisoned"))?;
+            let c = guard
+                .as_ref()
+                .ok_or_else(|| anyhow!("transaction already committed or rolled back"))?;
+            f(c)
+        })
+        .await?
+    }
+
+    async fn finish(&self, statement: &'static str) -> Result<()> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut guard = conn.lock().map_err(|_| anyhow!("transaction mutex poisoned"))?;
+            let c = guard
+                .take()
+                .ok_or_else(|| anyhow!("transaction already committed or rolled back"))?;
+            c.execute_batch(statement)?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await?
+    }
+}
+
+impl Drop for SqliteStoreTransaction {
+    fn drop(&mut self) {
+        if let Ok(mut guard) = self.conn.lock() {
+            if let Some(c) = guard.take() {
+                let _ = c.execute_batch("ROLLBACK");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StoreTransaction for SqliteStoreTransaction {
+    async fn upsert_data_source_document(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document: &Document,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document.document_id.clone();
+        let timestamp = document.timestamp as i64;
+        let tags = document.tags.clone();
+        let parents = document.parents.clone();
+        let source_url = document.source_url.clone();
+        let hash = document.hash.clone();
+        let text_size = document.text_size as i64;
+        let chunk_count = document.chunk_count as i64;
+        self.with_conn(move |c| {
+            let ds_row_id: i64 = c.query_row(
+                "SELECT id FROM data_sources WHERE project = ?1 AND data_source_id = ?2",
+                rusqlite::params![project_id, data_source_id],
+                |r| r.get(0),
+            )?;
+            c.execute(
+                "UPDATE data_sources_documents SET status = 'superseded'
+                 WHERE data_source = ?1 AND document_id = ?2 AND status = 'latest'",
+                rusqlite::params![ds_row_id, document_id],
+            )?;
+            c.execute(
+                "INSERT INTO data_sources_documents
+                 (data_source, created, document_id, timestamp, source_url, hash,
+                  text_size, chunk_count, status)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 'latest')",
+                rusqlite::params![
+                    ds_row_id,
+                    utils::now(),
+                    document_id,
+                    timestamp,
+                    source_url,
+                    hash,
+                    text_size,
+                    chunk_count
+                ],
+            )?;
+            let doc_row_id = c.last_insert_rowid();
+            for tag in &tags {
+                c.execute(
+                    "INSERT INTO data_sources_documents_tags (document, tag) VALUES (?1, ?2)",
+                    rusqlite::params![doc_row_id, tag],
+                )?;
+            }
+            for parent in &parents {
+                c.execute(
+                    "INSERT INTO data_sources_documents_parents (document, parent) VALUES (?1, ?2)",
+                    rusqlite::params![doc_row_id, parent],
+                )?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_data_source_document_parents(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document_id: &str,
+        parents: &Vec<String>,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let document_id = document_id.to_string();
+        let parents = parents.clone();
+        self.with_conn(move |c| {
+            let doc_row_id: i64 = c.query_row(
+                "SELECT d.id FROM data_sources_documents d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2
+                       AND d.document_id = ?3 AND d.status = 'latest'",
+                rusqlite::params![project_id, data_source_id, document_id],
+                |r| r.get(0),
+            )?;
+            c.execute(
+                "DELETE FROM data_sources_documents_parents WHERE document = ?1",
+                [doc_row_id],
+            )?;
+            for parent in &parents {
+                c.execute(
+                    "INSERT INTO data_sources_documents_parents (document, parent)
+                     VALUES (?1, ?2)",
+                    rusqlite::params![doc_row_id, parent],
+                )?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn upsert_database_table(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        name: &str,
+        description: &str,
+    ) -> Result<DatabaseTable> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let name = name.to_string();
+        let description = description.to_string();
+        self.with_conn(move |c| {
+            let db_row_id: i64 = c.query_row(
+                "SELECT d.id FROM databases d
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3",
+                rusqlite::params![project_id, data_source_id, database_id],
+                |r| r.get(0),
+            )?;
+            let created = utils::now();
+            c.execute(
+                "INSERT INTO databases_tables (created, database, table_id, name, description)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(table_id, database) DO UPDATE SET name = ?4, description = ?5",
+                rusqlite::params![created, db_row_id, table_id, name, description],
+            )?;
+            Ok(DatabaseTable::new(
+                created as u64,
+                &table_id,
+                &name,
+                &description,
+                None,
+            ))
+        })
+        .await
+    }
+
+    async fn update_database_table_schema(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        schema: &TableSchema,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let schema_json = serde_json::to_string(schema)?;
+        self.with_conn(move |c| {
+            c.execute(
+                "UPDATE databases_tables SET schema = ?1
+                 WHERE table_id = ?2 AND database = (
+                     SELECT d.id FROM databases d
+                     JOIN data_sources ds ON ds.id = d.data_source
+                     WHERE ds.project = ?3 AND ds.data_source_id = ?4 AND d.database_id = ?5
+                 )",
+                rusqlite::params![schema_json, table_id, project_id, data_source_id, database_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn batch_upsert_database_rows(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        rows: &Vec<DatabaseRow>,
+        truncate: bool,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let data_source_id = data_source_id.to_string();
+        let database_id = database_id.to_string();
+        let table_id = table_id.to_string();
+        let rows = rows
+            .iter()
+            .map(|r| Ok((r.row_id().to_string(), serde_json::to_string(r.content())?)))
+            .collect::<Result<Vec<(String, String)>>>()?;
+        self.with_conn(move |c| {
+            let table_row_id: i64 = c.query_row(
+                "SELECT t.id FROM databases_tables t
+                 JOIN databases d ON d.id = t.database
+                 JOIN data_sources ds ON ds.id = d.data_source
+                 WHERE ds.project = ?1 AND ds.data_source_id = ?2 AND d.database_id = ?3
+                       AND t.table_id = ?4",
+                rusqlite::params![project_id, data_source_id, database_id, table_id],
+                |r| r.get(0),
+            )?;
+            if truncate {
+                c.execute(
+                    "DELETE FROM databases_rows WHERE database_table = ?1",
+                    [table_row_id],
+                )?;
+            }
+            for (row_id, content_json) in &rows {
+                c.execute(
+                    "INSERT INTO databases_rows (created, database_table, content, row_id)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(row_id, database_table) DO UPDATE SET content = ?3",
+                    rusqlite::params![utils::now(), table_row_id, content_json, row_id],
+                )?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn append_run_block(
+        &self,
+        project: &Project,
+        run: &Run,
+        block_idx: usize,
+        block_type: &BlockType,
+        block_name: &String,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let run_id = run.run_id().to_string();
+        let block_type = block_type.to_string();
+        let block_name = block_name.clone();
+        let executions = run.execution_for_block(block_idx)?;
+        self.with_conn(move |c| {
+            let run_row_id: i64 = c.query_row(
+                "SELECT id FROM runs WHERE project = ?1 AND run_id = ?2",
+                rusqlite::params![project_id, run_id],
+                |r| r.get(0),
+            )?;
+            for (input_idx, map) in executions.into_iter().enumerate() {
+                for (map_idx, execution) in map.into_iter().enumerate() {
+                    let json = serde_json::to_string(&execution)?;
+                    let hash = utils::hash(json.as_bytes());
+                    c.execute(
+                        "INSERT OR IGNORE INTO block_executions (hash, execution)
+                         VALUES (?1, ?2)",
+                        rusqlite::params![hash, json],
+                    )?;
+                    let block_execution_id: i64 = c.query_row(
+                        "SELECT id FROM block_executions WHERE hash = ?1",
+                        [&hash],
+                        |r| r.get(0),
+                    )?;
+                    c.execute(
+                        "INSERT INTO runs_joins
+                         (run, block_idx, block_type, block_name, input_idx, map_idx, block_execution)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        rusqlite::params![
+                            run_row_id,
+                            block_idx as i64,
+                            block_type,
+                            block_name,
+                            input_idx as i64,
+                            map_idx as i64,
+                            block_execution_id
+                        ],
+                    )?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        self.finish("COMMIT").await
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        self.finish("ROLLBACK").await
+    }
+}
+
+// Appends an `AND id [NOT] IN (...)` fragment to `where_clause` for each of
+// `is_in`/`is_not`, pushing their values onto `params` in the same order so
+// the two stay in sync with the `?` placeholders. Used by
+// `find_data_source_document_ids` to apply a `SearchFilter`'s tag/parent
+// clauses against the `data_sources_documents_{tags,parents}` join tables.
+fn push_membership_clause(
+    where_clause: &mut String,
+    params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+    join_table: &str,
+    join_column: &str,
+    is_in: Option<&Vec<String>>,
+    is_not: Option<&Vec<String>>,
+) {
+    if let Some(values) = is_in.filter(|v| !v.is_empty()) {
+        let placeholders = values.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+        where_clause.push_str(&format!(
+            " AND id IN (SELECT document FROM {join_table} WHERE {join_column} IN ({placeholders}))"
+        ));
+        params.extend(values.iter().cloned().map(|v| Box::new(v) as Box<dyn rusqlite::ToSql>));
+    }
+    if let Some(values) = is_not.filter(|v| !v.is_empty()) {
+        let placeholders = values.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+        where_clause.push_str(&format!(
+            " AND id NOT IN (SELECT document FROM {join_table} WHERE {join_column} IN ({placeholders}))"
+        ));
+        params.extend(values.iter().cloned().map(|v| Box::new(v) as Box<dyn rusqlite::ToSql>));
+    }
+}
+
+// Assembles a `Document` from `data_sources_documents` plus its tag/parent
+// join tables, since SQLite has no array column to read them back from in
+// one shot.
+fn load_document_row(c: &rusqlite::Connection, row_id: i64) -> Result<Document> {
+    let (document_id, timestamp, source_url, hash, text_size, chunk_count, status): (
+        String,
+        i64,
+        Option<String>,
+        String,
+        i64,
+        i64,
+        String,
+    ) = c.query_row(
+        "SELECT document_id, timestamp, source_url, hash, text_size, chunk_count, status
+         FROM data_sources_documents WHERE id = ?1",
+        [row_id],
+        |r| {
+            Ok((
+                r.get(0)?,
+                r.get(1)?,
+                r.get(2)?,
+                r.get(3)?,
+                r.get(4)?,
+                r.get(5)?,
+                r.get(6)?,
+            ))
+        },
+    )?;
+    let tags = {
+        let mut stmt =
+            c.prepare("SELECT tag FROM data_sources_documents_tags WHERE document = ?1")?;
+        stmt.query_map([row_id], |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?
+    };
+    let parents = {
+        let mut stmt =
+            c.prepare("SELECT parent FROM data_sources_documents_parents WHERE document = ?1")?;
+        stmt.query_map([row_id], |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?
+    };
+    Ok(Document {
+        document_id,
+        timestamp: timestamp as u64,
+        tags,
+        parents,
+        source_url,
+        hash,
+        text_size: text_size as u64,
+        chunk_count: chunk_count as usize,
+        status: status.parse()?,
+        chunks: vec![],
+        text: None,
+    })
+}
+
+impl SqliteStore {
+    // Shared lookup for the four cache flavors above: they all read through
+    // the same `cache` table, keyed on the request hash. `max_age_ms`, when
+    // set, drops rows older than that threshold rather than serving them.
+    async fn cache_get<T: serde::de::DeserializeOwned + Send + 'static>(
+        &self,
+        project: &Project,
+        hash: &str,
+        max_age_ms: Option<u64>,
+    ) -> Result<Vec<T>> {
+        let project_id = project.project_id();
+        let hash = hash.to_string();
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            // `None` keeps entries pinned indefinitely by using a cutoff
+            // older than any `created` value could be.
+            let min_created = max_age_ms.map_or(i64::MIN, |max_age_ms| {
+                utils::now() as i64 - max_age_ms as i64
+            });
+            let mut stmt = c.prepare(
+                "SELECT response FROM cache
+                 WHERE project = ?1 AND hash = ?2 AND created >= ?3
+                 ORDER BY created DESC",
+            )?;
+            let out = stmt
+                .query_map(rusqlite::params![project_id, hash, min_created], |r| {
+                    r.get::<_, String>(0)
+                })?
+                .map(|json| Ok::<_, anyhow::Error>(serde_json::from_str(&json?)?))
+                .collect::<Result<Vec<_>>>()?;
+            Ok::<_, anyhow::Error>(out)
+        })
+        .await?
+    }
+
+    // Shared insert for the four cache flavors above: they all write through
+    // the same `cache` table, keyed on the request hash.
+    async fn cache_store<T: serde::Serialize>(
+        &self,
+        project: &Project,
+        hash: &str,
+        request_json: &str,
+        response: &T,
+    ) -> Result<()> {
+        let project_id = project.project_id();
+        let hash = hash.to_string();
+        let request_json = request_json.to_string();
+        let response_json = serde_json::to_string(response)?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            c.execute(
+                "INSERT INTO cache (project, created, hash, request, response)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![project_id, utils::now(), hash, request_json, response_json],
+            )?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Raw-inserts a run row and returns its internal rowid, bypassing
+    // `create_run_empty` (which needs a fully populated `Run`) since these
+    // tests only care about the `runs`/`runs_joins`/`block_executions` rows
+    // themselves.
+    fn insert_run(c: &rusqlite::Connection, project_id: i64, run_id: &str) -> Result<i64> {
+        c.execute(
+            "INSERT INTO runs (project, created, run_id, run_type, app_hash, config_json, status_json)
+             VALUES (?1, ?2, ?3, 'deploy', 'hash', '{}', '{}')",
+            rusqlite::params![project_id, utils::now(), run_id],
+        )?;
+        Ok(c.last_insert_rowid())
+    }
+
+    fn insert_block_execution(c: &rusqlite::Connection, hash: &str) -> Result<i64> {
+        c.execute(
+            "INSERT INTO block_executions (hash, execution) VALUES (?1, '{}')",
+            [hash],
+        )?;
+        Ok(c.last_insert_rowid())
+    }
+
+    fn insert_runs_join(
+        c: &rusqlite::Connection,
+        run_row_id: i64,
+        block_execution_id: i64,
+    ) -> Result<()> {
+        c.execute(
+            "INSERT INTO runs_joins
+             (run, block_idx, block_type, block_name, input_idx, map_idx, block_execution)
+             VALUES (?1, 0, 'code', 'block', 0, 0, ?2)",
+            rusqlite::params![run_row_id, block_execution_id],
+        )?;
+        Ok(())
+    }
+
+    fn block_execution_exists(c: &rusqlite::Connection, id: i64) -> Result<bool> {
+        Ok(c.query_row(
+            "SELECT COUNT(*) FROM block_executions WHERE id = ?1",
+            [id],
+            |r| r.get::<_, i64>(0),
+        )? > 0)
+    }
+
+    async fn block_execution_exists_async(store: &SqliteStore, id: i64) -> Result<bool> {
+        let pool = store.pool.clone();
+        tokio::task::spawn_blocking(move || block_execution_exists(&pool.get()?, id)).await?
+    }
+
+    #[tokio::test]
+    async fn delete_run_keeps_block_execution_shared_with_another_run() -> Result<()> {
+        let store = SqliteStore::new_in_memory().await?;
+        let project = store.create_project().await?;
+        let project_id = project.project_id();
+
+        let block_execution_id = {
+            let pool = store.pool.clone();
+            tokio::task::spawn_blocking(move || {
+                let c = pool.get()?;
+                let block_execution_id = insert_block_execution(&c, "shared-hash")?;
+                let run_a = insert_run(&c, project_id, "run-a")?;
+                let run_b = insert_run(&c, project_id, "run-b")?;
+                insert_runs_join(&c, run_a, block_execution_id)?;
+                insert_runs_join(&c, run_b, block_execution_id)?;
+                Ok::<_, anyhow::Error>(block_execution_id)
+            })
+            .await??
+        };
+
+        // Deleting run A must not drop the block_execution: run B still
+        // references it via `runs_joins`.
+        store.delete_run(&project, "run-a").await?;
+        let still_there = block_execution_exists_async(&store, block_execution_id).await?;
+        assert!(still_there, "block_execution shared with run-b was deleted early");
+
+        // Once the last referencing run is gone, the row is reclaimed.
+        store.delete_run(&project, "run-b").await?;
+        let still_there = block_execution_exists_async(&store, block_execution_id).await?;
+        assert!(!still_there, "orphaned block_execution was not deleted");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_project_cascades_without_foreign_key_violation() -> Result<()> {
+        let store = SqliteStore::new_in_memory().await?;
+        let project = store.create_project().await?;
+        let project_id = project.project_id();
+
+        let block_execution_id = {
+            let pool = store.pool.clone();
+            tokio::task::spawn_blocking(move || {
+                let c = pool.get()?;
+                let block_execution_id = insert_block_execution(&c, "shared-hash")?;
+                let run_a = insert_run(&c, project_id, "run-a")?;
+                let run_b = insert_run(&c, project_id, "run-b")?;
+                insert_runs_join(&c, run_a, block_execution_id)?;
+                insert_runs_join(&c, run_b, block_execution_id)?;
+                Ok::<_, anyhow::Error>(block_execution_id)
+            })
+            .await??
+        };
+
+        // Two runs in the same project sharing a block_execution used to
+        // trip the `FOREIGN KEY` constraint (or leave a dangling join)
+        // partway through the per-run delete loop.
+        store.delete_project(&project).await?;
+
+        let still_there = block_execution_exists_async(&store, block_execution_id).await?;
+        assert!(!still_there);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_data_source_document_tags_is_idempotent() -> Result<()> {
+        let store = SqliteStore::new_in_memory().await?;
+        let project = store.create_project().await?;
+        let project_id = project.project_id();
+
+        {
+            let pool = store.pool.clone();
+            tokio::task::spawn_blocking(move || {
+                let c = pool.get()?;
+                c.execute(
+                    "INSERT INTO data_sources (project, created, data_source_id, internal_id, config_json)
+                     VALUES (?1, ?2, 'ds', 'internal', '{}')",
+                    rusqlite::params![project_id, utils::now()],
+                )?;
+                let ds_row_id = c.last_insert_rowid();
+                c.execute(
+                    "INSERT INTO data_sources_documents
+                     (data_source, created, document_id, timestamp, source_url, hash,
+                      text_size, chunk_count, status)
+                     VALUES (?1, ?2, 'doc', ?2, NULL, 'hash', 0, 0, 'latest')",
+                    rusqlite::params![ds_row_id, utils::now()],
+                )?;
+                Ok::<_, anyhow::Error>(())
+            })
+            .await??;
+        }
+
+        let add = vec!["foo".to_string()];
+        let remove = vec![];
+        store
+            .update_data_source_document_tags(&project, "ds", "doc", &add, &remove)
+            .await?;
+        let tags = store
+            .update_data_source_document_tags(&project, "ds", "doc", &add, &remove)
+            .await?;
+
+        assert_eq!(tags, vec!["foo".to_string()], "re-adding a tag duplicated it");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn gc_evicts_orphans_but_protects_pinned_hashes() -> Result<()> {
+        let store = SqliteStore::new_in_memory().await?;
+        let project = store.create_project().await?;
+
+        let (pinned_id, orphan_id) = {
+            let pool = store.pool.clone();
+            tokio::task::spawn_blocking(move || {
+                let c = pool.get()?;
+                let pinned_id = insert_block_execution(&c, "pinned-hash")?;
+                let orphan_id = insert_block_execution(&c, "orphan-hash")?;
+                Ok::<_, anyhow::Error>((pinned_id, orphan_id))
+            })
+            .await??
+        };
+
+        store.pin(&project, "my-alias", "pinned-hash").await?;
+        store.gc(&project, SizeTargets { max_rows: None, max_bytes: None }).await?;
+
+        let pool = store.pool.clone();
+        let (pinned_survives, orphan_survives) = tokio::task::spawn_blocking(move || {
+            let c = pool.get()?;
+            Ok::<_, anyhow::Error>((
+                block_execution_exists(&c, pinned_id)?,
+                block_execution_exists(&c, orphan_id)?,
+            ))
+        })
+        .await??;
+
+        assert!(pinned_survives, "gc evicted a pinned block_execution");
+        assert!(!orphan_survives, "gc left an unreferenced block_execution behind");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn claim_next_job_lease_is_used_by_requeue_stale_jobs() -> Result<()> {
+        let store = SqliteStore::new_in_memory().await?;
+        let project = store.create_project().await?;
+
+        store
+            .enqueue_job(&project, "q", &serde_json::json!({"work": 1}))
+            .await?;
+
+        // Claim with a very short lease.
+        let job = store.claim_next_job("q", 1).await?.expect("job to claim");
+        assert_eq!(job.status(), JobStatus::Running);
+
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        // Even though the reaper's own `lease_ms` argument is huge, the
+        // job's own 1ms claimed lease should make it eligible for requeue.
+        let requeued = store.requeue_stale_jobs("q", 60_000).await?;
+        assert_eq!(requeued, 1, "stale job with an expired lease was not requeued");
+
+        let reclaimed = store.claim_next_job("q", 1).await?;
+        assert!(reclaimed.is_some(), "requeued job was not claimable again");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transaction_rolls_back_on_drop_without_commit() -> Result<()> {
+        let store = SqliteStore::new_in_memory().await?;
+        let project = store.create_project().await?;
+        let project_id = project.project_id();
+
+        {
+            let pool = store.pool.clone();
+            tokio::task::spawn_blocking(move || {
+                let c = pool.get()?;
+                c.execute(
+                    "INSERT INTO data_sources (project, created, data_source_id, internal_id, config_json)
+                     VALUES (?1, ?2, 'ds', 'internal', '{}')",
+                    rusqlite::params![project_id, utils::now()],
+                )?;
+                let ds_row_id = c.last_insert_rowid();
+                c.execute(
+                    "INSERT INTO data_sources_documents
+                     (data_source, created, document_id, timestamp, source_url, hash,
+                      text_size, chunk_count, status)
+                     VALUES (?1, ?2, 'doc', ?2, NULL, 'hash', 0, 0, 'latest')",
+                    rusqlite::params![ds_row_id, utils::now()],
+                )?;
+                Ok::<_, anyhow::Error>(())
+            })
+            .await??;
+        }
+
+        {
+            let tx = store.begin().await?;
+            tx.update_data_source_document_parents(&project, "ds", "doc", &vec!["p1".to_string()])
+                .await?;
+            // Dropped here without calling `commit()` — should roll back.
+        }
+
+        let pool = store.pool.clone();
+        let parent_count = tokio::task::spawn_blocking(move || {
+            pool.get()?.query_row(
+                "SELECT COUNT(*) FROM data_sources_documents_parents",
+                [],
+                |r| r.get::<_, i64>(0),
+            )
+        })
+        .await??;
+        assert_eq!(parent_count, 0, "uncommitted transaction was persisted");
+
+        // Now do the same thing but commit, to confirm the happy path works.
+        let tx = store.begin().await?;
+        tx.update_data_source_document_parents(&project, "ds", "doc", &vec!["p1".to_string()])
+            .await?;
+        tx.commit().await?;
+
+        let pool = store.pool.clone();
+        let parent_count = tokio::task::spawn_blocking(move || {
+            pool.get()?.query_row(
+                "SELECT COUNT(*) FROM data_sources_documents_parents",
+                [],
+                |r| r.get::<_, i64>(0),
+            )
+        })
+        .await??;
+        assert_eq!(parent_count, 1, "committed transaction was not persisted");
+
+        Ok(())
+    }
+}
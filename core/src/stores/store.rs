@@ -6,6 +6,7 @@ use crate::databases::database::{Database, DatabaseRow, DatabaseTable};
 use crate::databases::table_schema::TableSchema;
 use crate::dataset::Dataset;
 use crate::http::request::{HttpRequest, HttpResponse};
+use crate::job_queue::Job;
 use crate::project::Project;
 use crate::providers::embedder::{EmbedderRequest, EmbedderVector};
 use crate::providers::llm::{LLMChatGeneration, LLMChatRequest, LLMGeneration, LLMRequest};
@@ -14,6 +15,27 @@ use anyhow::Result;
 use async_trait::async_trait;
 use std::collections::HashMap;
 
+/// Row/byte ceilings passed to `Store::gc`. Either bound may be left unset
+/// to only enforce the other one; `None`/`None` makes `gc` a no-op beyond
+/// the orphan sweep.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeTargets {
+    pub max_rows: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+/// Row counts and estimated byte sizes for the content-addressed tables,
+/// as returned by `Store::stats`, so callers can decide when `gc` is due.
+#[derive(Debug, Clone, Default)]
+pub struct StoreStats {
+    pub cache_rows: u64,
+    pub cache_bytes: u64,
+    pub block_executions_rows: u64,
+    pub block_executions_bytes: u64,
+    pub datasets_points_rows: u64,
+    pub datasets_points_bytes: u64,
+}
+
 #[async_trait]
 pub trait Store {
     // Projects
@@ -239,10 +261,14 @@ pub trait Store {
         database_id: &str,
     ) -> Result<()>;
     // LLM Cache
+    // `max_age_ms`, when set, excludes rows older than that many milliseconds
+    // so a read never serves a response staler than the caller's freshness
+    // window; `None` keeps entries pinned indefinitely like before.
     async fn llm_cache_get(
         &self,
         project: &Project,
         request: &LLMRequest,
+        max_age_ms: Option<u64>,
     ) -> Result<Vec<LLMGeneration>>;
     async fn llm_cache_store(
         &self,
@@ -256,6 +282,7 @@ pub trait Store {
         &self,
         project: &Project,
         request: &LLMChatRequest,
+        max_age_ms: Option<u64>,
     ) -> Result<Vec<LLMChatGeneration>>;
     async fn llm_chat_cache_store(
         &self,
@@ -269,6 +296,7 @@ pub trait Store {
         &self,
         project: &Project,
         request: &EmbedderRequest,
+        max_age_ms: Option<u64>,
     ) -> Result<Vec<EmbedderVector>>;
     async fn embedder_cache_store(
         &self,
@@ -278,10 +306,13 @@ pub trait Store {
     ) -> Result<()>;
 
     // HTTP Cache
+    // External responses are the ones most likely to go stale underneath a
+    // long-lived cache row, so `max_age_ms` matters most here.
     async fn http_cache_get(
         &self,
         project: &Project,
         request: &HttpRequest,
+        max_age_ms: Option<u64>,
     ) -> Result<Vec<HttpResponse>>;
     async fn http_cache_store(
         &self,
@@ -290,6 +321,47 @@ pub trait Store {
         response: &HttpResponse,
     ) -> Result<()>;
 
+    // Explicit cache invalidation, complementing `max_age_ms` reads: drop a
+    // single request/response pair by its hash, or every row for `project`
+    // older than `older_than_ms` (returns the number of rows removed).
+    async fn invalidate_cache(&self, project: &Project, hash: &str) -> Result<()>;
+    async fn invalidate_cache_by_age(&self, project: &Project, older_than_ms: u64) -> Result<usize>;
+
+    // Job Queue
+    async fn enqueue_job(
+        &self,
+        project: &Project,
+        queue: &str,
+        payload: &serde_json::Value,
+    ) -> Result<i64>;
+    // `lease_ms` is stamped onto the claimed row (see `job_queue.lease_ms`),
+    // so `requeue_stale_jobs` reaps it against the lease the claimer actually
+    // asked for, not whatever lease a later, unrelated caller happens to pass.
+    async fn claim_next_job(&self, queue: &str, lease_ms: u64) -> Result<Option<Job>>;
+    async fn heartbeat_job(&self, job_id: i64) -> Result<()>;
+    async fn complete_job(&self, job_id: i64) -> Result<()>;
+    async fn fail_job(&self, job_id: i64, requeue: bool) -> Result<()>;
+    // Resets any `running` job of `queue` whose heartbeat is older than its
+    // own claimed `lease_ms` back to `new`, so a crashed worker's job is
+    // retried by the next `claim_next_job` call. `lease_ms` is only a
+    // fallback for rows whose stored `lease_ms` is 0.
+    // Returns the number of jobs requeued.
+    async fn requeue_stale_jobs(&self, queue: &str, lease_ms: u64) -> Result<usize>;
+
+    // Garbage Collection
+    // First sweeps `block_executions`/`datasets_points` rows with no surviving
+    // join reference, then (if still over `targets`) evicts `cache` rows by
+    // oldest `created` first. A `block_executions` hash pinned via `pin`
+    // survives the sweep even when unreferenced; pins do not affect the
+    // `cache` eviction.
+    async fn gc(&self, project: &Project, targets: SizeTargets) -> Result<()>;
+    async fn pin(&self, project: &Project, name: &str, hash: &str) -> Result<()>;
+    async fn unpin(&self, project: &Project, name: &str) -> Result<()>;
+    async fn stats(&self, project: &Project) -> Result<StoreStats>;
+
+    // Transactions
+    async fn begin(&self) -> Result<Box<dyn StoreTransaction + Sync + Send>>;
+
     // Cloning
     fn clone_box(&self) -> Box<dyn Store + Sync + Send>;
 }
@@ -300,7 +372,71 @@ impl Clone for Box<dyn Store + Sync + Send> {
     }
 }
 
-pub const POSTGRES_TABLES: [&'static str; 14] = [
+/// A handle on a single atomic unit of work spanning several `Store`
+/// mutations, e.g. "upsert a document and update its parents" or "upsert a
+/// table and its schema". Exposes the mutating subset of `Store` that's
+/// actually composed this way; read methods stay on `Store` since they
+/// don't need to share a transaction to be consistent with each other.
+///
+/// `commit` is never implicit: writes persist only once `commit` is called.
+/// If a `StoreTransaction` is dropped without `commit` or `rollback`
+/// having been called (an early return via `?`, a panic), the backing
+/// connection rolls back rather than silently committing a partial write.
+#[async_trait]
+pub trait StoreTransaction {
+    async fn upsert_data_source_document(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document: &Document,
+    ) -> Result<()>;
+    async fn update_data_source_document_parents(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        document_id: &str,
+        parents: &Vec<String>,
+    ) -> Result<()>;
+    async fn upsert_database_table(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        name: &str,
+        description: &str,
+    ) -> Result<DatabaseTable>;
+    async fn update_database_table_schema(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        schema: &TableSchema,
+    ) -> Result<()>;
+    async fn batch_upsert_database_rows(
+        &self,
+        project: &Project,
+        data_source_id: &str,
+        database_id: &str,
+        table_id: &str,
+        rows: &Vec<DatabaseRow>,
+        truncate: bool,
+    ) -> Result<()>;
+    async fn append_run_block(
+        &self,
+        project: &Project,
+        run: &Run,
+        block_idx: usize,
+        block_type: &BlockType,
+        block_name: &String,
+    ) -> Result<()>;
+
+    async fn commit(self: Box<Self>) -> Result<()>;
+    async fn rollback(self: Box<Self>) -> Result<()>;
+}
+
+pub const POSTGRES_TABLES: [&'static str; 16] = [
     "-- projects
      CREATE TABLE IF NOT EXISTS projects (
         id BIGSERIAL PRIMARY KEY
@@ -434,9 +570,30 @@ pub const POSTGRES_TABLES: [&'static str; 14] = [
        row_id               TEXT NOT NULL, -- unique within table
        FOREIGN KEY(database_table) REFERENCES databases_tables(id)
     );",
+    "-- durable job queue, claimed by `claim_next_job` with SKIP LOCKED so
+     -- multiple workers can pull from the same queue without double-processing
+     CREATE TABLE IF NOT EXISTS job_queue (
+       id                   BIGSERIAL PRIMARY KEY,
+       project              BIGINT NOT NULL,
+       queue                TEXT NOT NULL,
+       payload              JSONB NOT NULL,
+       status               TEXT NOT NULL,
+       heartbeat            BIGINT NOT NULL,
+       lease_ms             BIGINT NOT NULL DEFAULT 0,
+       created              BIGINT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+    "-- pins a name to a block_executions hash so `gc` never evicts it
+     CREATE TABLE IF NOT EXISTS alias (
+       id                   BIGSERIAL PRIMARY KEY,
+       project              BIGINT NOT NULL,
+       name                 TEXT NOT NULL,
+       hash                 TEXT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
 ];
 
-pub const SQL_INDEXES: [&'static str; 23] = [
+pub const SQL_INDEXES: [&'static str; 25] = [
     "CREATE INDEX IF NOT EXISTS
        idx_specifications_project_created ON specifications (project, created);",
     "CREATE INDEX IF NOT EXISTS
@@ -489,6 +646,10 @@ pub const SQL_INDEXES: [&'static str; 23] = [
         idx_databases_tables_database_table_name ON databases_tables (database, name);",
     "CREATE UNIQUE INDEX IF NOT EXISTS
         idx_databases_rows_row_id_database_table ON databases_rows (row_id, database_table);",
+    "CREATE INDEX IF NOT EXISTS
+        idx_job_queue_queue_status_heartbeat ON job_queue (queue, status, heartbeat);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+        idx_alias_project_name ON alias (project, name);",
 ];
 
 pub const SQL_FUNCTIONS: [&'static str; 3] = [
@@ -567,3 +728,242 @@ pub const SQL_FUNCTIONS: [&'static str; 3] = [
         $$ LANGUAGE plpgsql;
     "#,
 ];
+
+// SQLite translation of `POSTGRES_TABLES` for the `sqlite` feature
+// (see `stores::sqlite::SqliteStore`). `BIGSERIAL` becomes
+// `INTEGER PRIMARY KEY AUTOINCREMENT`, and the `TEXT[]` tag/parent arrays
+// on `data_sources_documents` are normalized into join tables
+// (`data_sources_documents_tags`, `data_sources_documents_parents`) since
+// SQLite has no array type or GIN index to match them against.
+pub const SQLITE_TABLES: [&'static str; 18] = [
+    "-- projects
+     CREATE TABLE IF NOT EXISTS projects (
+        id INTEGER PRIMARY KEY AUTOINCREMENT
+    );",
+    "-- app specifications
+    CREATE TABLE IF NOT EXISTS specifications (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       project              BIGINT NOT NULL,
+       created              BIGINT NOT NULL,
+       hash                 TEXT NOT NULL,
+       specification        TEXT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+    "-- datasets
+    CREATE TABLE IF NOT EXISTS datasets (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       project              BIGINT NOT NULL,
+       created              BIGINT NOT NULL,
+       dataset_id           TEXT NOT NULL,
+       hash                 TEXT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+    "-- datasets raw hashed data points
+    CREATE TABLE IF NOT EXISTS datasets_points (
+       id   INTEGER PRIMARY KEY AUTOINCREMENT,
+       hash TEXT NOT NULL,
+       json TEXT NOT NULL
+    );",
+    "-- datasets to data association (avoid duplication)
+    CREATE TABLE IF NOT EXISTS datasets_joins (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       dataset              BIGINT NOT NULL,
+       point                BIGINT NOT NULL,
+       point_idx            BIGINT NOT NULL,
+       FOREIGN KEY(dataset) REFERENCES datasets(id),
+       FOREIGN KEY(point)   REFERENCES datasets_points(id)
+    );",
+    "-- runs
+    CREATE TABLE IF NOT EXISTS runs (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       project              BIGINT NOT NULL,
+       created              BIGINT NOT NULL,
+       run_id               TEXT NOT NULL,
+       run_type             TEXT NOT NULL,
+       app_hash             TEXT NOT NULL,
+       config_json          TEXT NOT NULL,
+       status_json          TEXT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+    "-- block executions
+    CREATE TABLE IF NOT EXISTS block_executions (
+       id        INTEGER PRIMARY KEY AUTOINCREMENT,
+       hash      TEXT NOT NULL,
+       execution TEXT NOT NULL
+    );",
+    "-- runs to block_executions association (avoid duplication)
+    CREATE TABLE IF NOT EXISTS runs_joins (
+       id                           INTEGER PRIMARY KEY AUTOINCREMENT,
+       run                          BIGINT NOT NULL,
+       block_idx                    BIGINT NOT NULL,
+       block_type                   TEXT NOT NULL,
+       block_name                   TEXT NOT NULL,
+       input_idx                    BIGINT NOT NULL,
+       map_idx                      BIGINT NOT NULL,
+       block_execution              BIGINT NOT NULL,
+       FOREIGN KEY(run)             REFERENCES runs(id),
+       FOREIGN KEY(block_execution) REFERENCES block_executions(id)
+    );",
+    "-- Cache (non unique hash index)
+    CREATE TABLE IF NOT EXISTS cache (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       project              BIGINT NOT NULL,
+       created              BIGINT NOT NULL,
+       hash                 TEXT NOT NULL,
+       request              TEXT NOT NULL,
+       response             TEXT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+    "-- data sources
+    CREATE TABLE IF NOT EXISTS data_sources (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       project              BIGINT NOT NULL,
+       created              BIGINT NOT NULL,
+       data_source_id       TEXT NOT NULL,
+       internal_id          TEXT NOT NULL,
+       config_json          TEXT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+    "-- data sources documents (tags_array/parents moved to join tables, see below)
+    CREATE TABLE IF NOT EXISTS data_sources_documents (
+       id                       INTEGER PRIMARY KEY AUTOINCREMENT,
+       data_source              BIGINT NOT NULL,
+       created                  BIGINT NOT NULL,
+       document_id              TEXT NOT NULL,
+       timestamp                BIGINT NOT NULL,
+       source_url               TEXT,
+       hash                     TEXT NOT NULL,
+       text_size                BIGINT NOT NULL,
+       chunk_count              BIGINT NOT NULL,
+       status                   TEXT NOT NULL,
+       FOREIGN KEY(data_source) REFERENCES data_sources(id)
+    );",
+    "-- substitute for the `tags_array TEXT[]` GIN-indexed column
+    CREATE TABLE IF NOT EXISTS data_sources_documents_tags (
+       id                       INTEGER PRIMARY KEY AUTOINCREMENT,
+       document                 BIGINT NOT NULL,
+       tag                      TEXT NOT NULL,
+       FOREIGN KEY(document)    REFERENCES data_sources_documents(id)
+    );",
+    "-- substitute for the `parents TEXT[]` GIN-indexed column
+    CREATE TABLE IF NOT EXISTS data_sources_documents_parents (
+       id                       INTEGER PRIMARY KEY AUTOINCREMENT,
+       document                 BIGINT NOT NULL,
+       parent                   TEXT NOT NULL,
+       FOREIGN KEY(document)    REFERENCES data_sources_documents(id)
+    );",
+    "-- database
+    CREATE TABLE IF NOT EXISTS databases (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       created              BIGINT NOT NULL,
+       data_source          BIGINT NOT NULL,
+       database_id          TEXT NOT NULL, -- unique within data source. Used as the external id.
+       name                 TEXT NOT NULL, -- unique within data source
+       FOREIGN KEY(data_source) REFERENCES data_sources(id)
+    );",
+    "-- databases tables
+    CREATE TABLE IF NOT EXISTS databases_tables (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       created              BIGINT NOT NULL,
+       database             BIGINT NOT NULL,
+       table_id             TEXT NOT NULL, -- unique within database
+       name                 TEXT NOT NULL, -- unique within database
+       description          TEXT NOT NULL,
+       schema               TEXT, -- json, kept up-to-date automatically with the last insert
+       FOREIGN KEY(database) REFERENCES databases(id)
+    );",
+    "-- databases row
+    CREATE TABLE IF NOT EXISTS databases_rows (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       created              BIGINT NOT NULL,
+       database_table       BIGINT NOT NULL,
+       content              TEXT NOT NULL, -- json
+       row_id               TEXT NOT NULL, -- unique within table
+       FOREIGN KEY(database_table) REFERENCES databases_tables(id)
+    );",
+    "-- durable job queue, see POSTGRES_TABLES for the Postgres equivalent.
+     -- SQLite has no SKIP LOCKED; claim_next_job relies on its single-writer
+     -- transaction semantics instead (see SqliteStore::claim_next_job).
+     CREATE TABLE IF NOT EXISTS job_queue (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       project              BIGINT NOT NULL,
+       queue                TEXT NOT NULL,
+       payload              TEXT NOT NULL,
+       status               TEXT NOT NULL,
+       heartbeat            BIGINT NOT NULL,
+       lease_ms             BIGINT NOT NULL DEFAULT 0,
+       created              BIGINT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+    "-- pins a name to a block_executions hash so `gc` never evicts it
+     CREATE TABLE IF NOT EXISTS alias (
+       id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+       project              BIGINT NOT NULL,
+       name                 TEXT NOT NULL,
+       hash                 TEXT NOT NULL,
+       FOREIGN KEY(project) REFERENCES projects(id)
+    );",
+];
+
+// SQLite has no `CREATE INDEX ... USING GIN`; the tag/parent indexes below
+// cover the join tables that replace `tags_array`/`parents` instead.
+pub const SQLITE_INDEXES: [&'static str; 25] = [
+    "CREATE INDEX IF NOT EXISTS
+       idx_specifications_project_created ON specifications (project, created);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_specifications_project_hash ON specifications (project, hash);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_datasets_project_dataset_id_created
+       ON datasets (project, dataset_id, created);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_runs_project_run_type_created ON runs (project, run_type, created);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+       idx_runs_id ON runs (run_id);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+       idx_block_executions_hash ON block_executions (hash);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+       idx_datasets_points_hash ON datasets_points (hash);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_datasets_joins ON datasets_joins (dataset, point);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_runs_joins ON runs_joins (run, block_execution);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_cache_project_hash ON cache (project, hash);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+       idx_data_sources_project_data_source_id ON data_sources (project, data_source_id);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_data_sources_documents_data_source_document_id
+       ON data_sources_documents (data_source, document_id);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_data_sources_documents_data_source_status_timestamp
+       ON data_sources_documents (data_source, status, timestamp);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_data_sources_documents_data_source_document_id_hash
+       ON data_sources_documents (data_source, document_id, hash);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_data_sources_documents_data_source_document_id_status
+       ON data_sources_documents (data_source, document_id, status);",
+    "CREATE INDEX IF NOT EXISTS
+       idx_data_sources_documents_data_source_document_id_created
+       ON data_sources_documents (data_source, document_id, created DESC);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+       idx_data_sources_documents_tags_document
+       ON data_sources_documents_tags (document, tag);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+       idx_data_sources_documents_parents_document
+       ON data_sources_documents_parents (document, parent);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+        idx_databases_database_id_data_source ON databases (database_id, data_source);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+        idx_databases_data_source_database_name ON databases (data_source, name);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+        idx_databases_tables_table_id_database ON databases_tables (table_id, database);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+        idx_databases_tables_database_table_name ON databases_tables (database, name);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+        idx_databases_rows_row_id_database_table ON databases_rows (row_id, database_table);",
+    "CREATE INDEX IF NOT EXISTS
+        idx_job_queue_queue_status_heartbeat ON job_queue (queue, status, heartbeat);",
+    "CREATE UNIQUE INDEX IF NOT EXISTS
+        idx_alias_project_name ON alias (project, name);",
+];